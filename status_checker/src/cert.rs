@@ -0,0 +1,10 @@
+use std::time::{Duration, SystemTime};
+
+use x509_parser::parse_x509_certificate;
+
+/// The `notAfter` date of a DER-encoded certificate.
+pub(crate) fn expiry(der: &[u8]) -> Option<SystemTime> {
+    let (_, cert) = parse_x509_certificate(der).ok()?;
+    let not_after = u64::try_from(cert.validity().not_after.timestamp()).ok()?;
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(not_after))
+}
@@ -0,0 +1,721 @@
+use std::{
+    error::Error,
+    fmt, io, panic,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
+};
+
+use reqwest::{header::CONTENT_TYPE, redirect, tls::TlsInfo, Client};
+use tokio::{runtime::Runtime, sync::Semaphore};
+
+use crate::{
+    cert,
+    crawl::{is_html, Crawler},
+    queue::{HostLimits, Job, JobQueue},
+    rate::{RateLimiter, MIN_RATE},
+    retry, Attempt, CheckError, Phases, RetryPolicy, Target, Tracer, WebsiteStatus,
+};
+
+/// How requests reach the target: through a shared, pooled `reqwest` client,
+/// or over a fresh traced connection that records per-phase timings.
+#[derive(Clone)]
+pub enum Transport {
+    Pooled(Client),
+    Traced(Tracer),
+}
+
+pub(crate) struct Reply {
+    /// The URL that answered, after any redirects.
+    pub(crate) url: String,
+    pub(crate) status: u16,
+    pub(crate) content_type: Option<String>,
+    pub(crate) body: Option<Vec<u8>>,
+    phases: Option<Phases>,
+    cert_expiry: Option<SystemTime>,
+    retry_after: Option<Duration>,
+}
+
+/// Which response bodies `send` reads rather than discards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ReadBody {
+    Never,
+    /// Only bodies of HTML pages, as the crawler parses them for links.
+    Html,
+    Always,
+}
+
+async fn send(transport: &Transport, target: &Target, read_body: ReadBody) -> Result<Reply, CheckError> {
+    match transport {
+        Transport::Pooled(client) => {
+            let mut request = client
+                .request(target.method.clone(), &target.url)
+                .headers(target.headers.clone());
+            if let Some(body) = &target.body {
+                request = request.body(body.clone());
+            }
+            if let Some(timeout) = target.timeout {
+                request = request.timeout(timeout);
+            }
+            let resp = request.send().await?;
+            let url = resp.url().to_string();
+            let status = resp.status().as_u16();
+            let content_type = resp
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            let retry_after = retry::retry_after(resp.headers());
+            let cert_expiry = resp
+                .extensions()
+                .get::<TlsInfo>()
+                .and_then(|info| info.peer_certificate())
+                .and_then(cert::expiry);
+            let read_body = match read_body {
+                ReadBody::Never => false,
+                ReadBody::Html => is_html(content_type.as_deref()),
+                ReadBody::Always => true,
+            };
+            let body = if read_body { Some(resp.bytes().await?.to_vec()) } else { None };
+            Ok(Reply {
+                url,
+                status,
+                content_type,
+                body,
+                phases: None,
+                cert_expiry,
+                retry_after,
+            })
+        }
+        Transport::Traced(tracer) => {
+            let traced = tracer.send(target).await?;
+            Ok(Reply {
+                url: traced.url,
+                status: traced.status,
+                content_type: traced.content_type,
+                body: Some(traced.body),
+                phases: Some(traced.phases),
+                cert_expiry: traced.cert_expiry,
+                retry_after: traced.retry_after,
+            })
+        }
+    }
+}
+
+fn check_reply(target: &Target, reply: &Reply) -> Result<u16, CheckError> {
+    if !target.expect.contains(reply.status) {
+        return Err(CheckError::UnexpectedStatus(reply.status));
+    }
+    if let Some(body) = &reply.body {
+        let body = String::from_utf8_lossy(body);
+        for assertion in &target.assertions {
+            assertion.check(&body).map_err(CheckError::AssertionFailed)?;
+        }
+    }
+    Ok(reply.status)
+}
+
+/// Fetch the body of a document the checker reads itself, such as a sitemap.
+pub(crate) async fn fetch_body(transport: &Transport, url: &str) -> Result<Vec<u8>, CheckError> {
+    let reply = send(transport, &Target::new(url), ReadBody::Always).await?;
+    if !(200..=299).contains(&reply.status) {
+        return Err(CheckError::UnexpectedStatus(reply.status));
+    }
+    Ok(reply.body.unwrap_or_default())
+}
+
+pub async fn fetch_status(transport: &Transport, target: &Target, policy: &RetryPolicy) -> WebsiteStatus {
+    fetch(transport, target, policy, Pacing::default(), ReadBody::Never).await.0
+}
+
+/// What the attempts of a check wait for before they are sent.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Pacing<'a> {
+    /// Every attempt takes a token first.
+    pub(crate) limiter: Option<&'a RateLimiter>,
+    /// The queue the job was taken from. Retries keep to its per-host delay
+    /// like the first attempt did.
+    pub(crate) host: Option<(&'a JobQueue, &'a Job)>,
+}
+
+/// Check a target like `fetch_status`, also returning the final reply, whose
+/// body is read as `read_body` asks, or always when the target has assertions.
+pub(crate) async fn fetch(
+    transport: &Transport,
+    target: &Target,
+    policy: &RetryPolicy,
+    pacing: Pacing<'_>,
+    read_body: ReadBody,
+) -> (WebsiteStatus, Option<Reply>) {
+    let start = Instant::now();
+    let retries = target.retries.unwrap_or(policy.retries);
+    let read_body = if target.assertions.is_empty() { read_body } else { ReadBody::Always };
+    let mut attempts = vec![];
+
+    loop {
+        if let Some(limiter) = pacing.limiter {
+            limiter.acquire().await;
+        }
+        let attempt_start = Instant::now();
+        let (outcome, reply) = match send(transport, target, read_body).await {
+            Ok(reply) => (check_reply(target, &reply), Some(reply)),
+            Err(e) => (Err(e), None),
+        };
+        let duration = attempt_start.elapsed();
+
+        let retry = match &outcome {
+            Err(e) => attempts.len() < retries as usize && policy.retries_on(e),
+            Ok(_) => false,
+        };
+        if !retry {
+            attempts.push(Attempt {
+                outcome: outcome.clone(),
+                duration,
+                delay: None,
+            });
+            let status = WebsiteStatus {
+                url: target.url.clone(),
+                action_status: outcome,
+                response_time: duration,
+                total_time: start.elapsed(),
+                phases: reply.as_ref().and_then(|reply| reply.phases.clone()),
+                cert_expiry: reply.as_ref().and_then(|reply| reply.cert_expiry),
+                tags: target.tags.clone(),
+                referrer: target.referrer.clone(),
+                attempts,
+                timestamp: SystemTime::now(),
+            };
+            return (status, reply);
+        }
+
+        let now = Instant::now();
+        let mut resume = now + policy.delay(attempts.len() as u32, reply.and_then(|reply| reply.retry_after));
+        if let Some((queue, job)) = pacing.host {
+            resume = queue.retry_at(job, resume);
+        }
+        attempts.push(Attempt {
+            outcome,
+            duration,
+            delay: Some(resume - now),
+        });
+        tokio::time::sleep_until(resume.into()).await;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    /// One OS thread per worker, each running a single check at a time.
+    Threads,
+    /// Checks run as tasks on a small runtime, bounded by `concurrency`.
+    Async,
+}
+
+#[derive(Debug)]
+pub enum BuildError {
+    Client(reqwest::Error),
+    Tls(native_tls::Error),
+    Runtime(io::Error),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Client(e) => write!(f, "failed to build HTTP client: {}", e),
+            BuildError::Tls(e) => write!(f, "failed to set up TLS: {}", e),
+            BuildError::Runtime(e) => write!(f, "failed to start runtime: {}", e),
+        }
+    }
+}
+
+impl Error for BuildError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BuildError::Client(e) => Some(e),
+            BuildError::Tls(e) => Some(e),
+            BuildError::Runtime(e) => Some(e),
+        }
+    }
+}
+
+pub struct CheckerBuilder {
+    engine: Engine,
+    workers: usize,
+    concurrency: usize,
+    timeout: Duration,
+    retry: RetryPolicy,
+    user_agent: Option<String>,
+    max_redirects: usize,
+    accept_invalid_certs: bool,
+    phase_timings: bool,
+    interval: Duration,
+    jitter: f64,
+    host_limits: HostLimits,
+    rate_limit: Option<(f64, u32)>,
+}
+
+impl CheckerBuilder {
+    pub fn engine(mut self, engine: Engine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retry.retries = retries;
+        self
+    }
+
+    /// Replace the whole retry policy, including the number of `retries`.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    pub fn max_redirects(mut self, max_redirects: usize) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    pub fn accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.accept_invalid_certs = accept;
+        self
+    }
+
+    /// Measure DNS, connect, TLS, TTFB and download time for every check.
+    /// Each check then opens its own connection instead of reusing a pooled one.
+    pub fn phase_timings(mut self, enabled: bool) -> Self {
+        self.phase_timings = enabled;
+        self
+    }
+
+    /// How often watched targets are re-checked when they do not set their own interval.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Random delay added to each watch interval, as a fraction of the interval.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.max(0.0);
+        self
+    }
+
+    /// Most checks in flight against a single host at once, across all workers.
+    pub fn per_host_concurrency(mut self, max: usize) -> Self {
+        self.host_limits.max_in_flight = Some(max.max(1));
+        self
+    }
+
+    /// Least time between two requests to the same host, retries included.
+    pub fn per_host_delay(mut self, delay: Duration) -> Self {
+        self.host_limits.delay = delay;
+        self
+    }
+
+    /// Apply the per-host limits to the address a host resolves to, so that
+    /// names served from the same machine share them.
+    pub fn limit_by_ip(mut self, enabled: bool) -> Self {
+        self.host_limits.by_ip = enabled;
+        self
+    }
+
+    /// Most requests sent per second across all workers, allowing bursts of
+    /// up to `burst` requests after a quiet spell. Every attempt of a check
+    /// counts, including retries; redirects followed within an attempt do not.
+    /// Positive rates below `MIN_RATE` are raised to it.
+    pub fn rate_limit(mut self, per_second: f64, burst: u32) -> Self {
+        self.rate_limit = (per_second > 0.0).then_some((per_second.max(MIN_RATE), burst.max(1)));
+        self
+    }
+
+    pub fn build(self) -> Result<Checker, BuildError> {
+        let transport = if self.phase_timings {
+            let tracer = Tracer::new(
+                self.timeout,
+                self.user_agent.clone(),
+                self.max_redirects,
+                self.accept_invalid_certs,
+            )
+            .map_err(BuildError::Tls)?;
+            Transport::Traced(tracer)
+        } else {
+            let mut client = Client::builder()
+                .timeout(self.timeout)
+                .redirect(redirect::Policy::limited(self.max_redirects))
+                .danger_accept_invalid_certs(self.accept_invalid_certs)
+                .tls_info(true);
+            if let Some(user_agent) = &self.user_agent {
+                client = client.user_agent(user_agent);
+            }
+            Transport::Pooled(client.build().map_err(BuildError::Client)?)
+        };
+
+        // The thread pool only needs the runtime to drive connection I/O;
+        // the async engine runs every check on it.
+        let runtime_threads = match self.engine {
+            Engine::Threads => 1,
+            Engine::Async => self.workers,
+        };
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(runtime_threads)
+            .enable_all()
+            .build()
+            .map_err(BuildError::Runtime)?;
+
+        Ok(Checker {
+            transport,
+            runtime: Arc::new(runtime),
+            engine: self.engine,
+            workers: self.workers,
+            concurrency: self.concurrency,
+            retry: self.retry,
+            interval: self.interval,
+            jitter: self.jitter,
+            host_limits: self.host_limits,
+            rate_limit: self.rate_limit.map(|(rate, burst)| Arc::new(RateLimiter::new(rate, burst))),
+        })
+    }
+}
+
+impl Default for CheckerBuilder {
+    fn default() -> Self {
+        CheckerBuilder {
+            engine: Engine::Threads,
+            workers: thread::available_parallelism().map(|n| n.get()).unwrap_or(4),
+            concurrency: 100,
+            timeout: Duration::from_secs(5),
+            retry: RetryPolicy::default(),
+            user_agent: None,
+            max_redirects: 10,
+            accept_invalid_certs: false,
+            phase_timings: false,
+            interval: Duration::from_secs(60),
+            jitter: 0.1,
+            host_limits: HostLimits::default(),
+            rate_limit: None,
+        }
+    }
+}
+
+pub struct Checker {
+    pub(crate) transport: Transport,
+    pub(crate) runtime: Arc<Runtime>,
+    pub(crate) engine: Engine,
+    pub(crate) workers: usize,
+    pub(crate) concurrency: usize,
+    pub(crate) retry: RetryPolicy,
+    pub(crate) interval: Duration,
+    pub(crate) jitter: f64,
+    pub(crate) host_limits: HostLimits,
+    pub(crate) rate_limit: Option<Arc<RateLimiter>>,
+}
+
+impl Checker {
+    pub fn builder() -> CheckerBuilder {
+        CheckerBuilder::default()
+    }
+
+    /// Check every target once, yielding results as they complete. Targets
+    /// on the same host are held back as needed to respect the per-host limits,
+    /// and every request attempt waits for the rate limit.
+    pub fn check<I>(&self, targets: I) -> Results
+    where
+        I: IntoIterator,
+        I::Item: Into<Target>,
+    {
+        self.run(targets.into_iter().map(Into::into), None)
+    }
+
+    /// Check `targets` on the configured engine. A crawler is handed every
+    /// reply and may queue more targets before the job it came from finishes.
+    pub(crate) fn run(&self, targets: impl Iterator<Item = Target>, crawler: Option<Arc<Crawler>>) -> Results {
+        let job_queue = Arc::new(JobQueue::new(self.host_limits.clone(), self.runtime.handle().clone()));
+        for (index, target) in targets.enumerate() {
+            job_queue.push(index, target);
+        }
+        let (tx, rx) = mpsc::channel();
+        let workers = self.spawn_workers(job_queue, crawler, move |_, status| tx.send(status).is_ok());
+
+        Results {
+            rx,
+            workers,
+            _runtime: Arc::clone(&self.runtime),
+        }
+    }
+}
+
+type Done = Box<dyn Fn(&Job, WebsiteStatus) -> bool + Send + Sync>;
+
+/// Checks the jobs of one queue, shared by every worker of a run. Each result
+/// is handed to `done`, which returns `false` once results are no longer
+/// wanted; no further jobs are taken after that.
+struct Worker {
+    job_queue: Arc<JobQueue>,
+    transport: Transport,
+    retry: RetryPolicy,
+    limiter: Option<Arc<RateLimiter>>,
+    crawler: Option<Arc<Crawler>>,
+    done: Done,
+    stopped: AtomicBool,
+    /// Set when a check panicked, to be passed on once the run is joined.
+    panicked: Arc<AtomicBool>,
+}
+
+impl Worker {
+    async fn check(&self, job: Job) {
+        let mut finish = Finish {
+            worker: self,
+            job: &job,
+            checked: false,
+        };
+        let pacing = Pacing {
+            limiter: self.limiter.as_deref(),
+            host: Some((&self.job_queue, &job)),
+        };
+        let status = match &self.crawler {
+            Some(crawler) => crawler.check(&self.transport, &job.target, &self.retry, pacing, &self.job_queue).await,
+            None => fetch(&self.transport, &job.target, &self.retry, pacing, ReadBody::Never).await.0,
+        };
+        finish.checked = true;
+        drop(finish);
+        if !(self.done)(&job, status) {
+            self.stop();
+        }
+    }
+
+    fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        self.job_queue.close();
+    }
+
+    fn stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+}
+
+/// Finishes a job however its check ends. A check that panicked is dropped
+/// without a result, which stops the run rather than leaving the queue
+/// waiting on the job forever.
+struct Finish<'a> {
+    worker: &'a Worker,
+    job: &'a Job,
+    checked: bool,
+}
+
+impl Drop for Finish<'_> {
+    fn drop(&mut self) {
+        self.worker.job_queue.finish(self.job);
+        if !self.checked {
+            self.worker.panicked.store(true, Ordering::Relaxed);
+            self.worker.stop();
+        }
+    }
+}
+
+/// The workers of a run, joined once it has no more results.
+pub(crate) struct Workers {
+    handles: Vec<JoinHandle<()>>,
+    panicked: Arc<AtomicBool>,
+}
+
+impl Workers {
+    /// Wait for the worker threads, passing on the panic of any check.
+    pub(crate) fn join(&mut self) {
+        for handle in self.handles.drain(..) {
+            if let Err(panic) = handle.join() {
+                panic::resume_unwind(panic);
+            }
+        }
+        if self.panicked.load(Ordering::Relaxed) {
+            panic!("a check panicked before it produced a result");
+        }
+    }
+}
+
+impl Checker {
+    /// Check the jobs of `job_queue` on the configured engine until it is
+    /// finished, handing every result to `done`.
+    pub(crate) fn spawn_workers(
+        &self,
+        job_queue: Arc<JobQueue>,
+        crawler: Option<Arc<Crawler>>,
+        done: impl Fn(&Job, WebsiteStatus) -> bool + Send + Sync + 'static,
+    ) -> Workers {
+        let panicked = Arc::new(AtomicBool::new(false));
+        let worker = Arc::new(Worker {
+            job_queue,
+            transport: self.transport.clone(),
+            retry: self.retry.clone(),
+            limiter: self.rate_limit.clone(),
+            crawler,
+            done: Box::new(done),
+            stopped: AtomicBool::new(false),
+            panicked: Arc::clone(&panicked),
+        });
+
+        let handles = match self.engine {
+            Engine::Threads => (0..self.workers)
+                .map(|_| {
+                    let worker = Arc::clone(&worker);
+                    let runtime = Arc::clone(&self.runtime);
+                    thread::spawn(move || {
+                        while !worker.stopped()
+                            && let Some(job) = worker.job_queue.next_blocking()
+                        {
+                            runtime.block_on(worker.check(job));
+                        }
+                    })
+                })
+                .collect(),
+            Engine::Async => {
+                let semaphore = Arc::new(Semaphore::new(self.concurrency));
+                self.runtime.spawn(async move {
+                    loop {
+                        // Take a permit first so a job is only taken once it can run.
+                        let permit = Arc::clone(&semaphore).acquire_owned().await.unwrap();
+                        if worker.stopped() {
+                            break;
+                        }
+                        let Some(job) = worker.job_queue.next().await else {
+                            break;
+                        };
+                        let worker = Arc::clone(&worker);
+                        tokio::spawn(async move {
+                            worker.check(job).await;
+                            drop(permit);
+                        });
+                    }
+                });
+                vec![]
+            }
+        };
+        Workers { handles, panicked }
+    }
+}
+
+pub struct Results {
+    rx: mpsc::Receiver<WebsiteStatus>,
+    workers: Workers,
+    _runtime: Arc<Runtime>,
+}
+
+impl Iterator for Results {
+    type Item = WebsiteStatus;
+
+    fn next(&mut self) -> Option<WebsiteStatus> {
+        match self.rx.recv() {
+            Ok(status) => Some(status),
+            Err(_) => {
+                self.workers.join();
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        panic::{self, AssertUnwindSafe},
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use hyper::{Body, Response};
+    use reqwest::Client;
+    use tokio::runtime::Runtime;
+
+    use super::{Checker, Engine, Finish, Transport, Worker, Workers};
+    use crate::{
+        queue::{HostLimits, JobQueue},
+        server, RetryPolicy, Target,
+    };
+
+    #[test]
+    fn panicking_check_finishes_its_job_and_is_passed_on() {
+        let runtime = Runtime::new().unwrap();
+        let job_queue = Arc::new(JobQueue::new(HostLimits::default(), runtime.handle().clone()));
+        job_queue.push(0, Target::new("http://127.0.0.1:1/a"));
+        job_queue.push(1, Target::new("http://127.0.0.1:1/b"));
+        let panicked = Arc::new(AtomicBool::new(false));
+        let worker = Arc::new(Worker {
+            job_queue: Arc::clone(&job_queue),
+            transport: Transport::Pooled(Client::new()),
+            retry: RetryPolicy::default(),
+            limiter: None,
+            crawler: None,
+            done: Box::new(|_, _| true),
+            stopped: AtomicBool::new(false),
+            panicked: Arc::clone(&panicked),
+        });
+
+        let job = job_queue.next_blocking().unwrap();
+        let check = {
+            let worker = Arc::clone(&worker);
+            runtime.spawn(async move {
+                let _finish = Finish {
+                    worker: &worker,
+                    job: &job,
+                    checked: false,
+                };
+                panic!("check failed");
+            })
+        };
+        assert!(runtime.block_on(check).is_err());
+
+        // The queue is closed and has nothing in flight, so the run can end.
+        assert!(worker.stopped());
+        assert!(job_queue.next_blocking().is_none());
+        let mut workers = Workers { handles: vec![], panicked };
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| workers.join())).is_err());
+    }
+
+    #[test]
+    fn async_engine_stops_once_results_are_dropped() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let server = {
+            let requests = Arc::clone(&requests);
+            server::serve(SocketAddr::from(([127, 0, 0, 1], 0)), move |_| {
+                requests.fetch_add(1, Ordering::SeqCst);
+                async { Response::new(Body::empty()) }
+            })
+            .unwrap()
+        };
+        let checker = Checker::builder().engine(Engine::Async).concurrency(1).build().unwrap();
+        let targets: Vec<String> = (0..50).map(|i| format!("http://{}/{}", server.local_addr(), i)).collect();
+
+        let mut results = checker.check(targets);
+        assert!(results.next().is_some());
+        drop(results);
+        thread::sleep(Duration::from_millis(500));
+
+        assert!(requests.load(Ordering::SeqCst) < 5, "{} requests", requests.load(Ordering::SeqCst));
+    }
+}
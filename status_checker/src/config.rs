@@ -0,0 +1,494 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use regex::Regex;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Method,
+};
+use serde::{de, Deserialize, Deserializer};
+
+use crate::{normalize_url, Assertion, ExpectedStatus, Module, State, Target, Webhook};
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, String),
+    UnknownFormat(PathBuf),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "{}: {}", path.display(), e.trim_end()),
+            ConfigError::UnknownFormat(path) => write!(
+                f,
+                "{}: unknown config format, expected a .toml, .yaml or .yml file",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Io(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Targets loaded from a TOML or YAML file. Every target inherits the
+/// settings from the optional `defaults` section and may override them.
+/// Probe modules are self-contained and do not inherit `defaults`.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub targets: Vec<Target>,
+    pub webhooks: Vec<Webhook>,
+    pub modules: BTreeMap<String, Module>,
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> Result<Config, ConfigError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+
+        let raw: RawConfig = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&text).map_err(|e| ConfigError::Parse(path.to_path_buf(), e.to_string()))?,
+            Some("yaml" | "yml") => {
+                serde_yaml::from_str(&text).map_err(|e| ConfigError::Parse(path.to_path_buf(), e.to_string()))?
+            }
+            _ => return Err(ConfigError::UnknownFormat(path.to_path_buf())),
+        };
+
+        if raw.targets.is_empty() && raw.modules.is_empty() {
+            return Err(ConfigError::Parse(path.to_path_buf(), "no targets or modules defined".to_string()));
+        }
+
+        let defaults = raw.defaults;
+        let targets = raw.targets.into_iter().map(|target| target.into_target(&defaults)).collect();
+        let webhooks = raw.webhooks.into_iter().map(RawWebhook::into_webhook).collect();
+        let modules = raw
+            .modules
+            .into_iter()
+            .map(|(name, module)| (name, module.into_module()))
+            .collect();
+        Ok(Config {
+            targets,
+            webhooks,
+            modules,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    #[serde(default)]
+    defaults: RawDefaults,
+    #[serde(default)]
+    targets: Vec<RawTarget>,
+    #[serde(default)]
+    webhooks: Vec<RawWebhook>,
+    #[serde(default)]
+    modules: BTreeMap<String, RawModule>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawDefaults {
+    method: Option<HttpMethod>,
+    #[serde(default)]
+    headers: Headers,
+    body: Option<String>,
+    timeout: Option<Seconds>,
+    retries: Option<u32>,
+    expect: Option<ExpectedStatus>,
+    #[serde(default)]
+    body_contains: Vec<String>,
+    #[serde(default)]
+    body_not_contains: Vec<String>,
+    #[serde(default)]
+    body_matches: Vec<Pattern>,
+    #[serde(default)]
+    body_not_matches: Vec<Pattern>,
+    #[serde(default)]
+    tags: Vec<String>,
+    interval: Option<Seconds>,
+    degraded_after: Option<Seconds>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawTarget {
+    url: TargetUrl,
+    method: Option<HttpMethod>,
+    #[serde(default)]
+    headers: Headers,
+    body: Option<String>,
+    timeout: Option<Seconds>,
+    retries: Option<u32>,
+    expect: Option<ExpectedStatus>,
+    #[serde(default)]
+    body_contains: Vec<String>,
+    #[serde(default)]
+    body_not_contains: Vec<String>,
+    #[serde(default)]
+    body_matches: Vec<Pattern>,
+    #[serde(default)]
+    body_not_matches: Vec<Pattern>,
+    #[serde(default)]
+    tags: Vec<String>,
+    interval: Option<Seconds>,
+    degraded_after: Option<Seconds>,
+}
+
+impl RawTarget {
+    fn into_target(self, defaults: &RawDefaults) -> Target {
+        let mut headers = defaults.headers.0.clone();
+        headers.extend(self.headers.0);
+
+        let contains = defaults.body_contains.iter().chain(&self.body_contains);
+        let not_contains = defaults.body_not_contains.iter().chain(&self.body_not_contains);
+        let matches = defaults.body_matches.iter().chain(&self.body_matches);
+        let not_matches = defaults.body_not_matches.iter().chain(&self.body_not_matches);
+
+        let mut assertions = vec![];
+        assertions.extend(contains.map(|text| Assertion::Contains(text.clone())));
+        assertions.extend(not_contains.map(|text| Assertion::NotContains(text.clone())));
+        assertions.extend(matches.map(|pattern| Assertion::Matches(pattern.0.clone())));
+        assertions.extend(not_matches.map(|pattern| Assertion::NotMatches(pattern.0.clone())));
+
+        let mut tags = defaults.tags.clone();
+        tags.extend(self.tags);
+
+        Target {
+            url: self.url.0,
+            method: self
+                .method
+                .or_else(|| defaults.method.clone())
+                .map_or(Method::GET, |method| method.0),
+            headers,
+            body: self.body.or_else(|| defaults.body.clone()),
+            timeout: self.timeout.or(defaults.timeout).map(|timeout| timeout.0),
+            retries: self.retries.or(defaults.retries),
+            expect: self.expect.or_else(|| defaults.expect.clone()).unwrap_or_default(),
+            assertions,
+            tags,
+            interval: self.interval.or(defaults.interval).map(|interval| interval.0),
+            degraded_after: self
+                .degraded_after
+                .or(defaults.degraded_after)
+                .map(|degraded_after| degraded_after.0),
+            referrer: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawWebhook {
+    url: TargetUrl,
+    #[serde(default)]
+    headers: Headers,
+    timeout: Option<Seconds>,
+    retries: Option<u32>,
+    on: Option<Vec<State>>,
+    payload: Option<serde_json::Value>,
+}
+
+impl RawWebhook {
+    fn into_webhook(self) -> Webhook {
+        let mut webhook = Webhook::new(self.url.0);
+        webhook.headers = self.headers.0;
+        if let Some(timeout) = self.timeout {
+            webhook.timeout = timeout.0;
+        }
+        if let Some(retries) = self.retries {
+            webhook.retries = retries;
+        }
+        if let Some(on) = self.on {
+            webhook.on = on;
+        }
+        if let Some(payload) = self.payload {
+            webhook.payload = payload;
+        }
+        webhook
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawModule {
+    method: Option<HttpMethod>,
+    #[serde(default)]
+    headers: Headers,
+    body: Option<String>,
+    timeout: Option<Seconds>,
+    retries: Option<u32>,
+    expect: Option<ExpectedStatus>,
+    #[serde(default)]
+    body_contains: Vec<String>,
+    #[serde(default)]
+    body_not_contains: Vec<String>,
+    #[serde(default)]
+    body_matches: Vec<Pattern>,
+    #[serde(default)]
+    body_not_matches: Vec<Pattern>,
+}
+
+impl RawModule {
+    fn into_module(self) -> Module {
+        let mut module = Module::default();
+        if let Some(method) = self.method {
+            module.method = method.0;
+        }
+        module.headers = self.headers.0;
+        module.body = self.body;
+        module.timeout = self.timeout.map(|timeout| timeout.0);
+        module.retries = self.retries;
+        if let Some(expect) = self.expect {
+            module.expect = expect;
+        }
+        module.assertions.extend(self.body_contains.into_iter().map(Assertion::Contains));
+        module.assertions.extend(self.body_not_contains.into_iter().map(Assertion::NotContains));
+        module.assertions.extend(self.body_matches.into_iter().map(|pattern| Assertion::Matches(pattern.0)));
+        module.assertions.extend(self.body_not_matches.into_iter().map(|pattern| Assertion::NotMatches(pattern.0)));
+        module
+    }
+}
+
+struct TargetUrl(String);
+
+impl<'de> Deserialize<'de> for TargetUrl {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let url = String::deserialize(deserializer)?;
+        normalize_url(&url).map(TargetUrl).map_err(de::Error::custom)
+    }
+}
+
+#[derive(Clone)]
+struct HttpMethod(Method);
+
+impl<'de> Deserialize<'de> for HttpMethod {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let method = String::deserialize(deserializer)?;
+        Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+            .map(HttpMethod)
+            .map_err(|_| de::Error::custom(format!("invalid method '{}'", method)))
+    }
+}
+
+#[derive(Default)]
+struct Headers(HeaderMap);
+
+impl<'de> Deserialize<'de> for Headers {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in BTreeMap::<String, String>::deserialize(deserializer)? {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| de::Error::custom(format!("invalid header name '{}'", name)))?;
+            let value = HeaderValue::from_str(&value)
+                .map_err(|_| de::Error::custom(format!("invalid value for header '{}'", name)))?;
+            headers.insert(name, value);
+        }
+        Ok(Headers(headers))
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Seconds(Duration);
+
+impl<'de> Deserialize<'de> for Seconds {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let secs = f64::deserialize(deserializer)?;
+        Duration::try_from_secs_f64(secs)
+            .ok()
+            .filter(|duration| !duration.is_zero())
+            .map(Seconds)
+            .ok_or_else(|| de::Error::custom(format!("invalid duration '{}', expected a positive number of seconds", secs)))
+    }
+}
+
+struct Pattern(Regex);
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&pattern)
+            .map(Pattern)
+            .map_err(|e| de::Error::custom(format!("invalid regex '{}': {}", pattern, e)))
+    }
+}
+
+impl<'de> Deserialize<'de> for ExpectedStatus {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Code(u16),
+            List(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Code(code) => code.to_string().parse(),
+            Raw::List(list) => list.parse(),
+        }
+        .map_err(de::Error::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for State {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let state = String::deserialize(deserializer)?;
+        match state.as_str() {
+            "up" => Ok(State::Up),
+            "degraded" => Ok(State::Degraded),
+            "down" => Ok(State::Down),
+            _ => Err(de::Error::custom(format!(
+                "invalid state '{}', expected 'up', 'degraded' or 'down'",
+                state
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process, time::Duration};
+
+    use reqwest::Method;
+
+    use super::{Config, ConfigError};
+    use crate::Assertion;
+
+    /// Write `text` to a file named `name` in a scratch directory and load it.
+    fn load(name: &str, text: &str) -> Result<Config, ConfigError> {
+        let dir = std::env::temp_dir().join(format!("status_checker-config-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, text).unwrap();
+        let config = Config::load(&path);
+        fs::remove_dir_all(dir).unwrap();
+        config
+    }
+
+    fn describe(assertions: &[Assertion]) -> Vec<String> {
+        assertions
+            .iter()
+            .map(|assertion| match assertion {
+                Assertion::Contains(text) => format!("contains {}", text),
+                Assertion::NotContains(text) => format!("not contains {}", text),
+                Assertion::Matches(re) => format!("matches {}", re),
+                Assertion::NotMatches(re) => format!("not matches {}", re),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn targets_inherit_defaults() {
+        let config = load(
+            "merge.toml",
+            r#"
+[defaults]
+method = "head"
+timeout = 5
+retries = 2
+headers = { Authorization = "Bearer default", Accept = "text/html" }
+tags = ["prod"]
+body_contains = ["ok"]
+
+[[targets]]
+url = "example.com"
+
+[[targets]]
+url = "https://example.org/health"
+method = "post"
+timeout = 1.5
+headers = { authorization = "Bearer mine" }
+tags = ["api"]
+body_contains = ["healthy"]
+body_not_matches = ["error \\d+"]
+"#,
+        )
+        .unwrap();
+
+        let [plain, own] = &config.targets[..] else {
+            panic!("expected two targets, got {}", config.targets.len());
+        };
+        assert_eq!(plain.url, "https://example.com/");
+        assert_eq!(plain.method, Method::HEAD);
+        assert_eq!(plain.timeout, Some(Duration::from_secs(5)));
+        assert_eq!(plain.retries, Some(2));
+        assert_eq!(plain.headers["authorization"], "Bearer default");
+        assert_eq!(plain.tags, ["prod"]);
+        assert_eq!(describe(&plain.assertions), ["contains ok"]);
+
+        assert_eq!(own.method, Method::POST);
+        assert_eq!(own.timeout, Some(Duration::from_millis(1500)));
+        assert_eq!(own.retries, Some(2));
+        assert_eq!(own.headers.get_all("authorization").iter().collect::<Vec<_>>(), ["Bearer mine"]);
+        assert_eq!(own.headers["accept"], "text/html");
+        assert_eq!(own.tags, ["prod", "api"]);
+        assert_eq!(describe(&own.assertions), ["contains ok", "contains healthy", r"not matches error \d+"]);
+        assert_eq!(own.interval, None);
+    }
+
+    #[test]
+    fn loads_yaml() {
+        let config = load(
+            "merge.yaml",
+            r#"
+defaults:
+  interval: 30
+  degraded_after: 0.5
+  tags: [prod]
+targets:
+  - url: example.com
+    expect: 200-299,301
+    tags: [web]
+  - url: example.org
+    interval: 10
+"#,
+        )
+        .unwrap();
+
+        let [web, other] = &config.targets[..] else {
+            panic!("expected two targets, got {}", config.targets.len());
+        };
+        assert_eq!(web.expect.to_string(), "200-299,301");
+        assert_eq!(web.interval, Some(Duration::from_secs(30)));
+        assert_eq!(web.degraded_after, Some(Duration::from_millis(500)));
+        assert_eq!(web.tags, ["prod", "web"]);
+        assert_eq!(other.interval, Some(Duration::from_secs(10)));
+        assert_eq!(other.expect.to_string(), "200-399");
+        assert_eq!(other.tags, ["prod"]);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let error = load("bad.toml", "[defaults]\ntimeout = 5\n\n[[targets]]\nurl = \"example.com\"\ntimeout = -1\n").unwrap_err();
+        let message = error.to_string();
+        assert!(message.contains("line 6"), "{}", message);
+        assert!(message.contains("invalid duration '-1'"), "{}", message);
+
+        let error = load("bad.yaml", "targets:\n  - url: example.com\n    retries: lots\n").unwrap_err();
+        let message = error.to_string();
+        assert!(message.contains("targets[0].retries"), "{}", message);
+        assert!(message.contains("line 3"), "{}", message);
+
+        let error = load("unknown.toml", "[[targets]]\nurl = \"example.com\"\nbogus = 1\n").unwrap_err();
+        assert!(error.to_string().contains("line 3"), "{}", error);
+        assert!(error.to_string().contains("unknown field `bogus`"), "{}", error);
+
+        assert!(matches!(load("empty.toml", ""), Err(ConfigError::Parse(_, message)) if message == "no targets or modules defined"));
+        assert!(matches!(load("targets.json", "{}"), Err(ConfigError::UnknownFormat(_))));
+    }
+}
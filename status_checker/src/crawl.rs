@@ -0,0 +1,234 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, Mutex},
+};
+
+use regex::Regex;
+use reqwest::Method;
+use url::{Origin, Url};
+
+use crate::{
+    checker::{fetch, Pacing, ReadBody},
+    normalize_url,
+    queue::JobQueue,
+    Checker, Results, RetryPolicy, Target, Transport, WebsiteStatus,
+};
+
+/// How far `Checker::crawl` follows the links it finds.
+#[derive(Debug, Clone)]
+pub struct Crawl {
+    /// Pages this many links away from the start page are checked but not
+    /// parsed for further links.
+    pub max_depth: usize,
+    /// Skip links to other origins instead of checking them. Pages on other
+    /// origins are never parsed either way.
+    pub same_origin: bool,
+}
+
+impl Default for Crawl {
+    fn default() -> Self {
+        Crawl {
+            max_depth: 3,
+            same_origin: false,
+        }
+    }
+}
+
+static TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)<!--.*?-->|<(a|link|img|script)\b[^>]*>").unwrap());
+static ATTRIBUTE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)\s(href|src|rel)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#).unwrap());
+
+/// Every URL referenced by an `<a href>`, `<link href>`, `<img src>` or
+/// `<script src>` in `html`, resolved against the page it came from.
+fn links(base: &Url, html: &str) -> Vec<String> {
+    let mut links = vec![];
+    for tag in TAG.captures_iter(html) {
+        // Comments are matched too, so that links inside them are skipped.
+        let Some(name) = tag.get(1) else {
+            continue;
+        };
+        let name = name.as_str().to_ascii_lowercase();
+        let wanted = if name == "a" || name == "link" { "href" } else { "src" };
+
+        let mut value = None;
+        let mut rel = String::new();
+        for attribute in ATTRIBUTE.captures_iter(&tag[0]) {
+            let text = attribute.get(2).or(attribute.get(3)).or(attribute.get(4)).map_or("", |m| m.as_str());
+            match attribute[1].to_ascii_lowercase().as_str() {
+                "rel" => rel = text.to_ascii_lowercase(),
+                attr if attr == wanted && value.is_none() => value = Some(text.replace("&amp;", "&")),
+                _ => {}
+            }
+        }
+
+        // Connection hints name an origin rather than a resource to fetch.
+        if rel.split_whitespace().any(|rel| rel == "preconnect" || rel == "dns-prefetch") {
+            continue;
+        }
+        // Only web links are checked, not `mailto:`, `javascript:` and the like.
+        if let Some(resolved) = value.and_then(|value| base.join(value.trim()).ok())
+            && matches!(resolved.scheme(), "http" | "https")
+            && let Ok(link) = normalize_url(resolved.as_str())
+        {
+            links.push(link);
+        }
+    }
+    links
+}
+
+pub(crate) fn is_html(content_type: Option<&str>) -> bool {
+    content_type.is_some_and(|content_type| {
+        let content_type = content_type.to_ascii_lowercase();
+        content_type.starts_with("text/html") || content_type.starts_with("application/xhtml+xml")
+    })
+}
+
+/// Follows the links of pages on the start page's origin, queueing every URL
+/// it has not seen before along with the page that referenced it.
+pub(crate) struct Crawler {
+    options: Crawl,
+    /// Unset when the start URL does not parse, so that nothing is crawled.
+    origin: Option<Origin>,
+    /// Settings every found link is checked with, taken from the start page.
+    template: Target,
+    /// How many links away from the start page each queued URL was found.
+    depths: Mutex<HashMap<String, usize>>,
+}
+
+impl Crawler {
+    fn same_origin(&self, url: &str) -> bool {
+        Url::parse(url).is_ok_and(|url| self.origin.as_ref() == Some(&url.origin()))
+    }
+
+    /// Check a target, then queue the links on it if it is a page to crawl.
+    /// Links are queued before the job finishes, so the queue stays open.
+    pub(crate) async fn check(
+        &self,
+        transport: &Transport,
+        target: &Target,
+        policy: &RetryPolicy,
+        pacing: Pacing<'_>,
+        queue: &Arc<JobQueue>,
+    ) -> WebsiteStatus {
+        let depth = self.depths.lock().unwrap().get(&target.url).copied().unwrap_or(0);
+        let crawls = depth < self.options.max_depth && self.same_origin(&target.url);
+        let read_body = if crawls { ReadBody::Html } else { ReadBody::Never };
+        let (status, reply) = fetch(transport, target, policy, pacing, read_body).await;
+
+        let Some(reply) = reply.filter(|reply| crawls && status.is_up() && is_html(reply.content_type.as_deref())) else {
+            return status;
+        };
+        // A page that redirected off the origin is not crawled.
+        let Some(base) = Url::parse(&reply.url).ok().filter(|base| self.origin.as_ref() == Some(&base.origin())) else {
+            return status;
+        };
+        let body = String::from_utf8_lossy(reply.body.as_deref().unwrap_or_default());
+        for link in links(&base, &body) {
+            if self.options.same_origin && !self.same_origin(&link) {
+                continue;
+            }
+            let mut depths = self.depths.lock().unwrap();
+            if depths.contains_key(&link) {
+                continue;
+            }
+            // Found links are numbered in the order they were found, after the start page.
+            let index = depths.len();
+            depths.insert(link.clone(), depth + 1);
+            drop(depths);
+
+            queue.push(index, Target {
+                url: link,
+                referrer: Some(target.url.clone()),
+                ..self.template.clone()
+            });
+        }
+        status
+    }
+}
+
+impl Checker {
+    /// Check the start page and every page, image, script and stylesheet it
+    /// links to, following links on the same origin up to `max_depth` pages
+    /// deep. Every URL is checked once, and its result names the page that
+    /// first linked to it. Found links are checked with the start target's
+    /// headers, timeout, retries and expected status, but not its assertions.
+    pub fn crawl(&self, start: impl Into<Target>, options: Crawl) -> Results {
+        let mut start = start.into();
+        if let Ok(url) = normalize_url(&start.url) {
+            start.url = url;
+        }
+        let crawler = Crawler {
+            options,
+            origin: Url::parse(&start.url).ok().map(|url| url.origin()),
+            template: Target {
+                method: Method::GET,
+                body: None,
+                assertions: vec![],
+                ..start.clone()
+            },
+            depths: Mutex::new(HashMap::from([(start.url.clone(), 0)])),
+        };
+        self.run(std::iter::once(start), Some(Arc::new(crawler)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::links;
+
+    fn links_on(html: &str) -> Vec<String> {
+        links(&Url::parse("https://example.com/docs/index.html").unwrap(), html)
+    }
+
+    #[test]
+    fn finds_links_of_each_tag() {
+        let html = r#"
+            <A HREF="/about">About</A>
+            <link rel="stylesheet" href='style.css'>
+            <img alt="logo" src=../logo.png>
+            <script src="https://cdn.example.net/app.js"></script>
+            <a name="anchor">no link</a>
+        "#;
+        assert_eq!(links_on(html), [
+            "https://example.com/about",
+            "https://example.com/docs/style.css",
+            "https://example.com/logo.png",
+            "https://cdn.example.net/app.js",
+        ]);
+    }
+
+    #[test]
+    fn resolves_relative_links() {
+        let html = r#"<a href="page.html#top"><a href="?q=1"><a href="//other.example/x"><a href=" ./a/../b.html ">"#;
+        assert_eq!(links_on(html), [
+            "https://example.com/docs/page.html",
+            "https://example.com/docs/index.html?q=1",
+            "https://other.example/x",
+            "https://example.com/docs/b.html",
+        ]);
+    }
+
+    #[test]
+    fn unescapes_ampersands() {
+        assert_eq!(links_on(r#"<a href="search?a=1&amp;b=2">"#), ["https://example.com/docs/search?a=1&b=2"]);
+    }
+
+    #[test]
+    fn skips_comments_hints_and_other_schemes() {
+        let html = r#"
+            <!-- <a href="/commented"> -->
+            <!--
+              <img src="/also-commented.png">
+            -->
+            <link rel="preconnect" href="https://fonts.example">
+            <link rel="dns-prefetch" href="//cdn.example">
+            <a href="mailto:team@example.com">Mail</a>
+            <a href="javascript:void(0)">Menu</a>
+            <a href="tel:+100">Call</a>
+            <a href="/kept">Kept</a>
+        "#;
+        assert_eq!(links_on(html), ["https://example.com/kept"]);
+    }
+}
@@ -0,0 +1,167 @@
+use std::{error::Error, fmt, io};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckError {
+    Dns(String),
+    ConnectRefused,
+    ConnectTimeout,
+    Connect(String),
+    Tls(String),
+    ReadTimeout,
+    TooManyRedirects,
+    InvalidUrl(String),
+    Body(String),
+    UnexpectedStatus(u16),
+    AssertionFailed(String),
+    Other(String),
+}
+
+impl CheckError {
+    /// Every name `kind` can return.
+    pub const KINDS: [&'static str; 12] = [
+        "dns",
+        "connect_refused",
+        "connect_timeout",
+        "connect",
+        "tls",
+        "read_timeout",
+        "too_many_redirects",
+        "invalid_url",
+        "body",
+        "unexpected_status",
+        "assertion_failed",
+        "other",
+    ];
+
+    /// Stable, machine-readable name of the failure class.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CheckError::Dns(_) => "dns",
+            CheckError::ConnectRefused => "connect_refused",
+            CheckError::ConnectTimeout => "connect_timeout",
+            CheckError::Connect(_) => "connect",
+            CheckError::Tls(_) => "tls",
+            CheckError::ReadTimeout => "read_timeout",
+            CheckError::TooManyRedirects => "too_many_redirects",
+            CheckError::InvalidUrl(_) => "invalid_url",
+            CheckError::Body(_) => "body",
+            CheckError::UnexpectedStatus(_) => "unexpected_status",
+            CheckError::AssertionFailed(_) => "assertion_failed",
+            CheckError::Other(_) => "other",
+        }
+    }
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckError::Dns(cause) => write!(f, "dns error: {}", cause),
+            CheckError::ConnectRefused => write!(f, "connection refused"),
+            CheckError::ConnectTimeout => write!(f, "connect timed out"),
+            CheckError::Connect(cause) => write!(f, "connect error: {}", cause),
+            CheckError::Tls(cause) => write!(f, "tls error: {}", cause),
+            CheckError::ReadTimeout => write!(f, "request timed out"),
+            CheckError::TooManyRedirects => write!(f, "too many redirects"),
+            CheckError::InvalidUrl(cause) => write!(f, "invalid url: {}", cause),
+            CheckError::Body(cause) => write!(f, "body error: {}", cause),
+            CheckError::UnexpectedStatus(code) => write!(f, "unexpected status {}", code),
+            CheckError::AssertionFailed(cause) => write!(f, "assertion failed: {}", cause),
+            CheckError::Other(cause) => write!(f, "{}", cause),
+        }
+    }
+}
+
+impl Error for CheckError {}
+
+impl From<reqwest::Error> for CheckError {
+    fn from(e: reqwest::Error) -> Self {
+        let cause = root_cause(&e);
+
+        if e.is_builder() {
+            return CheckError::InvalidUrl(cause);
+        }
+        if e.is_redirect() {
+            return CheckError::TooManyRedirects;
+        }
+        if e.is_connect() {
+            return classify_connect(&e, cause);
+        }
+        if e.is_timeout() {
+            return CheckError::ReadTimeout;
+        }
+        if e.is_body() || e.is_decode() {
+            return CheckError::Body(cause);
+        }
+        CheckError::Other(cause)
+    }
+}
+
+fn classify_connect(e: &reqwest::Error, cause: String) -> CheckError {
+    let mut source = e.source();
+    while let Some(err) = source {
+        if err.to_string().starts_with("dns error") {
+            return CheckError::Dns(cause);
+        }
+        if err.is::<native_tls::Error>() {
+            return CheckError::Tls(cause);
+        }
+        if let Some(io_err) = err.downcast_ref::<io::Error>() {
+            match io_err.kind() {
+                io::ErrorKind::ConnectionRefused => return CheckError::ConnectRefused,
+                io::ErrorKind::TimedOut => return CheckError::ConnectTimeout,
+                _ => {}
+            }
+        }
+        source = err.source();
+    }
+
+    if e.is_timeout() {
+        CheckError::ConnectTimeout
+    } else {
+        CheckError::Connect(cause)
+    }
+}
+
+fn root_cause(e: &dyn Error) -> String {
+    let mut err = e;
+    while let Some(source) = err.source() {
+        err = source;
+    }
+    err.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use tokio::runtime::Runtime;
+
+    use super::CheckError;
+
+    /// Send a GET to `url` and convert the failure.
+    fn error_for(url: &str) -> CheckError {
+        let runtime = Runtime::new().unwrap();
+        let e = runtime
+            .block_on(reqwest::Client::new().get(url).send())
+            .expect_err("request should fail");
+        CheckError::from(e)
+    }
+
+    #[test]
+    fn closed_port_is_connect_refused() {
+        // Bind to get a free port, then close it so nothing is listening there.
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let error = error_for(&format!("http://{}/", addr));
+        assert_eq!(error, CheckError::ConnectRefused);
+        assert_eq!(error.kind(), "connect_refused");
+    }
+
+    #[test]
+    fn unparsable_url_is_invalid_url() {
+        for url in ["not a url", "http://exa mple.com/", "http://[::1/"] {
+            let error = error_for(url);
+            assert!(matches!(error, CheckError::InvalidUrl(_)), "{}: {:?}", url, error);
+            assert_eq!(error.kind(), "invalid_url");
+        }
+    }
+}
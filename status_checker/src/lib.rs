@@ -0,0 +1,37 @@
+mod cert;
+mod checker;
+mod config;
+mod crawl;
+mod error;
+mod metrics;
+mod output;
+mod probe;
+mod queue;
+mod rate;
+mod retry;
+mod server;
+mod sitemap;
+mod state;
+mod status;
+mod target;
+mod trace;
+mod watch;
+mod webhook;
+
+pub use checker::{fetch_status, BuildError, Checker, CheckerBuilder, Engine, Results, Transport};
+pub use config::{Config, ConfigError};
+pub use crawl::Crawl;
+pub use error::CheckError;
+pub use metrics::Metrics;
+pub use output::{write_csv, write_json, write_junit, Format, NdjsonWriter, SCHEMA_VERSION};
+pub use probe::{Module, DEFAULT_MODULE};
+pub use rate::MIN_RATE;
+pub use retry::RetryPolicy;
+pub use server::HttpServer;
+pub use sitemap::SitemapError;
+pub use state::{State, Transition, WatchEvent};
+pub use status::{Attempt, Phases, WebsiteStatus};
+pub use target::{normalize_url, Assertion, ExpectedStatus, Target};
+pub use trace::Tracer;
+pub use watch::Watch;
+pub use webhook::{Webhook, WebhookSink};
@@ -1,203 +1,823 @@
 use std::{
-    collections::VecDeque,
+    collections::{BTreeMap, HashSet},
     env,
     fs::File,
-    io::{self, BufRead, Write},
-    sync::{mpsc, Arc, Mutex},
-    thread,
-    time::{Duration, Instant, SystemTime},
+    io::{self, BufRead},
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
-use reqwest::blocking::Client;
-
-#[derive(Debug)]
-struct WebsiteStatus {
-    url: String,
-    action_status: Result<u16, String>,
-    response_time: Duration,
-    timestamp: SystemTime,
-}
+use regex::Regex;
+use status_checker::{
+    normalize_url, write_csv, write_json, write_junit, Assertion, CheckError, Checker, Config, Crawl, Engine, ExpectedStatus,
+    Format, Metrics, NdjsonWriter, Phases, RetryPolicy, Target, Transition, WatchEvent, WebhookSink, WebsiteStatus, MIN_RATE,
+};
 
-fn fetch_status(client: &Client, url: &str, retries: u32) -> WebsiteStatus {
-    let start = Instant::now();
-    let mut attempts = 0;
+// The process exits with 0 when every check passed, or when failures stayed
+// within `--max-failures` and `--min-success-ratio`.
 
-    while attempts <= retries {
-        let res = client.get(url).send();
-        let elapsed = start.elapsed();
+/// More checks failed than the thresholds allow.
+const EXIT_DOWN: i32 = 1;
+/// Invalid arguments or configuration.
+const EXIT_USAGE: i32 = 2;
+/// The checker could not run, e.g. a report could not be written.
+const EXIT_INTERNAL: i32 = 3;
 
-        match res {
-            Ok(resp) => {
-                return WebsiteStatus {
-                    url: url.to_string(),
-                    action_status: Ok(resp.status().as_u16()),
-                    response_time: elapsed,
-                    timestamp: SystemTime::now(),
-                };
-            }
-            Err(e) if attempts == retries => {
-                return WebsiteStatus {
-                    url: url.to_string(),
-                    action_status: Err(e.to_string()),
-                    response_time: elapsed,
-                    timestamp: SystemTime::now(),
-                };
-            }
-            _ => {
-                attempts += 1;
-                thread::sleep(Duration::from_millis(100));
-            }
-        }
-    }
-
-    unreachable!()
+struct Args {
+    watch: bool,
+    serve: bool,
+    crawl: Option<Crawl>,
+    urls: Vec<String>,
+    config: Option<String>,
+    sitemaps: Vec<String>,
+    sample: Option<usize>,
+    expect: ExpectedStatus,
+    assertions: Vec<Assertion>,
+    engine: Engine,
+    workers: usize,
+    concurrency: usize,
+    timeout: u64,
+    retry: RetryPolicy,
+    interval: u64,
+    timings: bool,
+    per_host: Option<usize>,
+    per_host_delay: Duration,
+    per_ip: bool,
+    rate: Option<f64>,
+    burst: u32,
+    metrics_listen: Option<SocketAddr>,
+    listen: SocketAddr,
+    output: String,
+    csv_output: String,
+    junit_output: String,
+    ndjson_output: String,
+    formats: Vec<Format>,
+    max_failures: usize,
+    min_success_ratio: Option<f64>,
 }
 
-fn write_json(results: &[WebsiteStatus]) {
-    let mut file = File::create("status.json").unwrap();
-    writeln!(file, "[").unwrap();
-
-    for (i, result) in results.iter().enumerate() {
-        let action_status_str = match &result.action_status {
-            Ok(code) => format!("\"action_status\": {{ \"Ok\": {} }}", code),
-            Err(e) => format!("\"action_status\": {{ \"Err\": \"{}\" }}", e),
-        };
-
-        let timestamp_str = match result.timestamp.duration_since(SystemTime::UNIX_EPOCH) {
-            Ok(duration) => format!("{}", duration.as_secs()),
-            Err(_) => "0".to_string(),
-        };
-
-        writeln!(file, "  {{").unwrap();
-        writeln!(file, "    \"url\": \"{}\",", result.url).unwrap();
-        writeln!(file, "    {},", action_status_str).unwrap();
-        writeln!(file, "    \"response_time_ms\": {},", result.response_time.as_millis()).unwrap();
-        writeln!(file, "    \"timestamp\": \"{}\"", timestamp_str).unwrap();
-        writeln!(file, "  }}{}", if i == results.len() - 1 { "" } else { "," }).unwrap();
+/// The value following the option at `args[*i]`, moving `i` onto it. An
+/// option given as the last argument is a usage error.
+fn value_of<'a>(args: &'a [String], i: &mut usize) -> &'a str {
+    *i += 1;
+    match args.get(*i) {
+        Some(value) => value,
+        None => {
+            eprintln!("Missing value for {}", args[*i - 1]);
+            std::process::exit(EXIT_USAGE);
+        }
     }
-
-    writeln!(file, "]").unwrap();
 }
 
-
-fn parse_args() -> (Vec<String>, usize, u64, u32) {
+fn parse_args() -> Args {
     let args: Vec<String> = env::args().collect();
     let mut urls = vec![];
+    let mut config = None;
+    let mut sitemaps = vec![];
+    let mut sample = None;
+    let mut expect = ExpectedStatus::default();
+    let mut assertions = vec![];
+    let mut engine = Engine::Threads;
     let mut workers = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4);
+    let mut concurrency = 100;
     let mut timeout = 5;
-    let mut retries = 0;
+    let mut retry = RetryPolicy::default();
+    let mut interval = 60;
+    let mut timings = false;
+    let mut per_host = None;
+    let mut per_host_delay = Duration::ZERO;
+    let mut per_ip = false;
+    let mut rate = None;
+    let mut burst = 1;
+    let mut metrics_listen = None;
+    let mut listen = SocketAddr::from(([127, 0, 0, 1], 9115));
+    let mut output = "status.json".to_string();
+    let mut csv_output = "status.csv".to_string();
+    let mut junit_output = "status.xml".to_string();
+    let mut ndjson_output = "status.ndjson".to_string();
+    let mut formats = vec![Format::Json];
+    let mut max_failures = None;
+    let mut min_success_ratio = None;
 
-    let mut i = 1;
+    let watch = args.get(1).is_some_and(|arg| arg == "watch");
+    let serve = args.get(1).is_some_and(|arg| arg == "serve");
+    let mut crawl = args.get(1).is_some_and(|arg| arg == "crawl").then(Crawl::default);
+    let mut i = if watch || serve || crawl.is_some() { 2 } else { 1 };
     while i < args.len() {
         match args[i].as_str() {
             "--file" => {
-                i += 1;
-                if i < args.len() {
-                    if let Ok(lines) = read_lines(&args[i]) {
-                        for line in lines.flatten() {
-                            if !line.trim().is_empty() && !line.trim().starts_with('#') {
-                                urls.push(line.trim().to_string());
-                            }
-                        }
+                let value = value_of(&args, &mut i);
+                let lines = match read_lines(value) {
+                    Ok(lines) => lines,
+                    Err(e) => {
+                        eprintln!("failed to read {}: {}", if value == "-" { "stdin" } else { value }, e);
+                        std::process::exit(EXIT_USAGE);
+                    }
+                };
+                let name = if value == "-" { "<stdin>" } else { value };
+                for (number, line) in lines.iter().enumerate() {
+                    if !line.trim().is_empty() && !line.trim().starts_with('#') {
+                        urls.push((format!("{}:{}", name, number + 1), line.trim().to_string()));
                     }
                 }
             }
-            "--workers" => {
-                i += 1;
-                if i < args.len() {
-                    workers = args[i].parse().unwrap_or(workers);
+            "--depth" => {
+                let Some(crawl) = &mut crawl else {
+                    eprintln!("--depth only applies to crawl");
+                    std::process::exit(EXIT_USAGE);
+                };
+                let value = value_of(&args, &mut i);
+                crawl.max_depth = match value.parse() {
+                    Ok(depth) => depth,
+                    Err(_) => {
+                        eprintln!("Invalid --depth '{}', expected a number of links", value);
+                        std::process::exit(EXIT_USAGE);
+                    }
+                };
+            }
+            "--same-origin" => {
+                let Some(crawl) = &mut crawl else {
+                    eprintln!("--same-origin only applies to crawl");
+                    std::process::exit(EXIT_USAGE);
+                };
+                crawl.same_origin = true;
+            }
+            "--sitemap" => {
+                let value = value_of(&args, &mut i);
+                match normalize_url(value) {
+                    Ok(url) => sitemaps.push(url),
+                    Err(e) => {
+                        eprintln!("Invalid --sitemap: {}", e);
+                        std::process::exit(EXIT_USAGE);
+                    }
                 }
             }
+            "--sample" => {
+                let value = value_of(&args, &mut i);
+                sample = match value.parse() {
+                    Ok(n) if n > 0 => Some(n),
+                    _ => {
+                        eprintln!("Invalid --sample '{}', expected a positive number", value);
+                        std::process::exit(EXIT_USAGE);
+                    }
+                };
+            }
+            "--config" => {
+                let value = value_of(&args, &mut i);
+                config = Some(value.to_string());
+            }
+            "--workers" => {
+                let value = value_of(&args, &mut i);
+                workers = match value.parse() {
+                    Ok(n) if n > 0 => n,
+                    _ => {
+                        eprintln!("Invalid --workers '{}', expected a positive number", value);
+                        std::process::exit(EXIT_USAGE);
+                    }
+                };
+            }
+            "--engine" => {
+                let value = value_of(&args, &mut i);
+                engine = match value {
+                    "threads" => Engine::Threads,
+                    "async" => Engine::Async,
+                    other => {
+                        eprintln!("Unknown engine '{}', expected 'threads' or 'async'", other);
+                        std::process::exit(EXIT_USAGE);
+                    }
+                };
+            }
+            "--concurrency" => {
+                let value = value_of(&args, &mut i);
+                concurrency = match value.parse() {
+                    Ok(n) if n > 0 => n,
+                    _ => {
+                        eprintln!("Invalid --concurrency '{}', expected a positive number", value);
+                        std::process::exit(EXIT_USAGE);
+                    }
+                };
+            }
             "--timeout" => {
-                i += 1;
-                if i < args.len() {
-                    timeout = args[i].parse().unwrap_or(timeout);
-                }
+                let value = value_of(&args, &mut i);
+                timeout = match value.parse() {
+                    Ok(secs) if secs > 0 => secs,
+                    _ => {
+                        eprintln!("Invalid --timeout '{}', expected a positive number of seconds", value);
+                        std::process::exit(EXIT_USAGE);
+                    }
+                };
             }
             "--retries" => {
-                i += 1;
-                if i < args.len() {
-                    retries = args[i].parse().unwrap_or(retries);
+                let value = value_of(&args, &mut i);
+                retry.retries = match value.parse() {
+                    Ok(retries) => retries,
+                    _ => {
+                        eprintln!("Invalid --retries '{}', expected a number", value);
+                        std::process::exit(EXIT_USAGE);
+                    }
+                };
+            }
+            "--retry-base" => {
+                let value = value_of(&args, &mut i);
+                retry.base = parse_seconds("--retry-base", value);
+            }
+            "--retry-max" => {
+                let value = value_of(&args, &mut i);
+                retry.max = parse_seconds("--retry-max", value);
+            }
+            "--retry-jitter" => {
+                let value = value_of(&args, &mut i);
+                retry.jitter = match value.parse::<f64>() {
+                    Ok(jitter) if (0.0..=1.0).contains(&jitter) => jitter,
+                    _ => {
+                        eprintln!("Invalid --retry-jitter '{}', expected a number between 0 and 1", value);
+                        std::process::exit(EXIT_USAGE);
+                    }
+                };
+            }
+            "--retry-on-status" => {
+                let value = value_of(&args, &mut i);
+                let codes = value.split(',').map(str::trim).filter(|code| !code.is_empty());
+                retry.statuses = match codes.map(str::parse).collect() {
+                    Ok(statuses) => statuses,
+                    Err(_) => {
+                        eprintln!("Invalid --retry-on-status '{}', expected a list of status codes", value);
+                        std::process::exit(EXIT_USAGE);
+                    }
+                };
+            }
+            "--retry-on-error" => {
+                let value = value_of(&args, &mut i);
+                let kinds = value.split(',').map(str::trim).filter(|kind| !kind.is_empty());
+                retry.errors = kinds.map(String::from).collect();
+                if let Some(kind) = retry.errors.iter().find(|kind| !CheckError::KINDS.contains(&kind.as_str())) {
+                    eprintln!("Invalid --retry-on-error kind '{}', expected one of {}", kind, CheckError::KINDS.join(", "));
+                    std::process::exit(EXIT_USAGE);
                 }
             }
+            "--ignore-retry-after" => retry.retry_after = false,
+            "--interval" => {
+                let value = value_of(&args, &mut i);
+                interval = match value.parse() {
+                    Ok(secs) if secs > 0 => secs,
+                    _ => {
+                        eprintln!("Invalid --interval '{}', expected a positive number of seconds", value);
+                        std::process::exit(EXIT_USAGE);
+                    }
+                };
+            }
+            "--timings" => timings = true,
+            "--per-host" => {
+                let value = value_of(&args, &mut i);
+                per_host = match value.parse() {
+                    Ok(max) if max > 0 => Some(max),
+                    _ => {
+                        eprintln!("Invalid --per-host '{}', expected a positive number", value);
+                        std::process::exit(EXIT_USAGE);
+                    }
+                };
+            }
+            "--per-host-delay" => {
+                let value = value_of(&args, &mut i);
+                per_host_delay = parse_seconds("--per-host-delay", value);
+            }
+            "--per-ip" => per_ip = true,
+            "--rate" => {
+                let value = value_of(&args, &mut i);
+                rate = match value.parse::<f64>() {
+                    Ok(rate) if rate >= MIN_RATE && rate.is_finite() => Some(rate),
+                    _ => {
+                        eprintln!("Invalid --rate '{}', expected a number of requests per second of at least {}", value, MIN_RATE);
+                        std::process::exit(EXIT_USAGE);
+                    }
+                };
+            }
+            "--burst" => {
+                let value = value_of(&args, &mut i);
+                burst = match value.parse() {
+                    Ok(burst) if burst > 0 => burst,
+                    _ => {
+                        eprintln!("Invalid --burst '{}', expected a positive number", value);
+                        std::process::exit(EXIT_USAGE);
+                    }
+                };
+            }
+            "--metrics-listen" => {
+                let value = value_of(&args, &mut i);
+                metrics_listen = match value.parse() {
+                    Ok(addr) => Some(addr),
+                    Err(e) => {
+                        eprintln!("Invalid --metrics-listen '{}': {}", value, e);
+                        std::process::exit(EXIT_USAGE);
+                    }
+                };
+            }
+            "--listen" => {
+                let value = value_of(&args, &mut i);
+                listen = match value.parse() {
+                    Ok(addr) => addr,
+                    Err(e) => {
+                        eprintln!("Invalid --listen '{}': {}", value, e);
+                        std::process::exit(EXIT_USAGE);
+                    }
+                };
+            }
+            "--output" => {
+                let value = value_of(&args, &mut i);
+                output = value.to_string();
+            }
+            "--csv-output" => {
+                let value = value_of(&args, &mut i);
+                csv_output = value.to_string();
+            }
+            "--junit-output" => {
+                let value = value_of(&args, &mut i);
+                junit_output = value.to_string();
+            }
+            "--ndjson-output" => {
+                let value = value_of(&args, &mut i);
+                ndjson_output = value.to_string();
+            }
+            "--format" => {
+                let value = value_of(&args, &mut i);
+                formats = match value.split(',').map(|format| format.trim().parse()).collect() {
+                    Ok(formats) => formats,
+                    Err(e) => {
+                        eprintln!("Invalid --format '{}': {}", value, e);
+                        std::process::exit(EXIT_USAGE);
+                    }
+                };
+            }
+            "--max-failures" => {
+                let value = value_of(&args, &mut i);
+                max_failures = match value.parse() {
+                    Ok(max) => Some(max),
+                    Err(_) => {
+                        eprintln!("Invalid --max-failures '{}', expected a number", value);
+                        std::process::exit(EXIT_USAGE);
+                    }
+                };
+            }
+            "--min-success-ratio" => {
+                let value = value_of(&args, &mut i);
+                min_success_ratio = match value.parse::<f64>() {
+                    Ok(ratio) if (0.0..=1.0).contains(&ratio) => Some(ratio),
+                    _ => {
+                        eprintln!("Invalid --min-success-ratio '{}', expected a number between 0 and 1", value);
+                        std::process::exit(EXIT_USAGE);
+                    }
+                };
+            }
+            "--expect" => {
+                let value = value_of(&args, &mut i);
+                expect = match value.parse() {
+                    Ok(expect) => expect,
+                    Err(e) => {
+                        eprintln!("Invalid --expect '{}': {}", value, e);
+                        std::process::exit(EXIT_USAGE);
+                    }
+                };
+            }
+            "--body-contains" => {
+                let value = value_of(&args, &mut i);
+                assertions.push(Assertion::Contains(value.to_string()));
+            }
+            "--body-not-contains" => {
+                let value = value_of(&args, &mut i);
+                assertions.push(Assertion::NotContains(value.to_string()));
+            }
+            "--body-matches" => {
+                let value = value_of(&args, &mut i);
+                assertions.push(Assertion::Matches(parse_regex(value)));
+            }
+            "--body-not-matches" => {
+                let value = value_of(&args, &mut i);
+                assertions.push(Assertion::NotMatches(parse_regex(value)));
+            }
+            arg if arg.starts_with('-') => {
+                eprintln!("Unknown option '{}'", arg);
+                std::process::exit(EXIT_USAGE);
+            }
             _ => {
-                urls.push(args[i].clone());
+                urls.push((format!("argument {}", i), args[i].clone()));
             }
         }
         i += 1;
     }
 
-    if urls.is_empty() {
-        eprintln!("Usage: website_checker [--file sites.txt] [URL ...] [--workers N] [--timeout S] [--retries N]");
-        std::process::exit(2);
+    // Every entry is rejected up front rather than failing as a check, and
+    // equivalent spellings of a URL, in several files or inline, are checked once.
+    let mut seen = HashSet::new();
+    let mut rejected = false;
+    let mut normalized = vec![];
+    for (location, url) in urls {
+        match normalize_url(&url) {
+            Ok(url) => {
+                if seen.insert(url.clone()) {
+                    normalized.push(url);
+                }
+            }
+            Err(e) => {
+                eprintln!("{}: {}", location, e);
+                rejected = true;
+            }
+        }
     }
+    if rejected {
+        std::process::exit(EXIT_USAGE);
+    }
+    let urls = normalized;
 
-    (urls, workers, timeout, retries)
-}
+    if crawl.is_some() && (urls.len() != 1 || !sitemaps.is_empty() || config.is_some()) {
+        eprintln!("crawl takes a single start URL");
+        std::process::exit(EXIT_USAGE);
+    }
 
-fn read_lines(path: &str) -> io::Result<io::Lines<io::BufReader<File>>> {
-    let file = File::open(path)?;
-    Ok(io::BufReader::new(file).lines())
-}
+    if urls.is_empty() && sitemaps.is_empty() && config.is_none() && !serve {
+        eprintln!("Usage: website_checker [watch [--interval S] [--metrics-listen ADDR] | serve [--listen ADDR] | crawl [--depth N] [--same-origin]] [--config checks.toml] [--file sites.txt|- ...] [--sitemap URL [--sample N]] [URL ...] [--workers N] [--engine threads|async] [--concurrency N] [--timeout S] [--retries N] [--retry-base S] [--retry-max S] [--retry-jitter F] [--retry-on-status CODES] [--retry-on-error KINDS] [--ignore-retry-after] [--timings] [--per-host N] [--per-host-delay S] [--per-ip] [--rate N] [--burst N] [--format json,csv,junit,ndjson] [--output PATH|-] [--csv-output PATH|-] [--junit-output PATH|-] [--ndjson-output PATH|-] [--max-failures N] [--min-success-ratio R] [--expect CODES] [--body-contains TEXT] [--body-not-contains TEXT] [--body-matches REGEX] [--body-not-matches REGEX]");
+        std::process::exit(EXIT_USAGE);
+    }
 
-fn main() {
-    let (urls, worker_count, timeout, retries) = parse_args();
+    let args = Args {
+        watch,
+        serve,
+        crawl,
+        urls,
+        config,
+        sitemaps,
+        sample,
+        expect,
+        assertions,
+        engine,
+        workers,
+        concurrency,
+        timeout,
+        retry,
+        interval,
+        timings,
+        per_host,
+        per_host_delay,
+        per_ip,
+        rate,
+        burst,
+        metrics_listen,
+        listen,
+        output,
+        csv_output,
+        junit_output,
+        ndjson_output,
+        formats,
+        // Once a ratio is given it decides on its own, unless --max-failures is also set.
+        max_failures: max_failures.unwrap_or(if min_success_ratio.is_some() { usize::MAX } else { 0 }),
+        min_success_ratio,
+    };
 
-    let job_queue = Arc::new(Mutex::new(VecDeque::from(urls)));
-    let (tx, rx) = mpsc::channel();
+    if args.formats.iter().filter(|format| args.output_of(**format) == "-").count() > 1 {
+        eprintln!("Only one report can be written to stdout");
+        std::process::exit(EXIT_USAGE);
+    }
 
-    let client = Arc::new(
-        Client::builder()
-            .timeout(Duration::from_secs(timeout))
-            .build()
-            .unwrap(),
-    );
+    args
+}
 
-    let mut handles = vec![];
+impl Args {
+    fn output_of(&self, format: Format) -> &str {
+        match format {
+            Format::Json => &self.output,
+            Format::Csv => &self.csv_output,
+            Format::Junit => &self.junit_output,
+            Format::Ndjson => &self.ndjson_output,
+        }
+    }
 
-    for _ in 0..worker_count {
-        let job_queue = Arc::clone(&job_queue);
-        let tx = tx.clone();
-        let client = Arc::clone(&client);
+    /// Whether a report goes to stdout, in which case the live results are
+    /// printed to stderr instead.
+    fn report_to_stdout(&self) -> bool {
+        self.formats.iter().any(|format| self.output_of(*format) == "-")
+    }
+}
 
-        let handle = thread::spawn(move || {
-            loop {
-                let url_opt = {
-                    let mut queue = job_queue.lock().unwrap();
-                    queue.pop_front()
-                };
+fn parse_seconds(flag: &str, value: &str) -> Duration {
+    match value.parse::<f64>().ok().and_then(|secs| Duration::try_from_secs_f64(secs).ok()) {
+        Some(duration) => duration,
+        None => {
+            eprintln!("Invalid {} '{}', expected a number of seconds", flag, value);
+            std::process::exit(EXIT_USAGE);
+        }
+    }
+}
+
+fn parse_regex(pattern: &str) -> Regex {
+    match Regex::new(pattern) {
+        Ok(re) => re,
+        Err(e) => {
+            eprintln!("Invalid regex '{}': {}", pattern, e);
+            std::process::exit(EXIT_USAGE);
+        }
+    }
+}
+
+/// Every line of a file, or of stdin when the path is `-`.
+fn read_lines(path: &str) -> io::Result<Vec<String>> {
+    if path == "-" {
+        return io::stdin().lock().lines().collect();
+    }
+    io::BufReader::new(File::open(path)?).lines().collect()
+}
 
-                match url_opt {
-                    Some(url) => {
-                        let status = fetch_status(&client, &url, retries);
-                        tx.send(status).unwrap();
+/// Pages listed in the `--sitemap`s that were not also given directly,
+/// narrowed to a random `--sample` kept in sitemap order.
+fn load_sitemaps(checker: &Checker, args: &Args) -> Vec<String> {
+    let mut seen: HashSet<String> = args.urls.iter().cloned().collect();
+    let mut pages = vec![];
+    for sitemap in &args.sitemaps {
+        let urls = match checker.sitemap(sitemap) {
+            Ok(urls) => urls,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(EXIT_USAGE);
+            }
+        };
+        for url in urls {
+            match normalize_url(&url) {
+                Ok(url) => {
+                    if seen.insert(url.clone()) {
+                        pages.push(url);
                     }
-                    None => break,
                 }
+                Err(e) => eprintln!("{}: skipping {}", sitemap, e),
             }
-        });
+        }
+    }
 
-        handles.push(handle);
+    match args.sample {
+        Some(n) if n < pages.len() => {
+            let mut picked = rand::seq::index::sample(&mut rand::thread_rng(), pages.len(), n).into_vec();
+            picked.sort_unstable();
+            picked.into_iter().map(|index| pages[index].clone()).collect()
+        }
+        _ => pages,
     }
+}
 
-    drop(tx); // Close channel
+fn format_phases(phases: &Phases) -> String {
+    let mut parts = vec![
+        format!("dns {}ms", phases.dns.as_millis()),
+        format!("connect {}ms", phases.connect.as_millis()),
+    ];
+    if let Some(tls) = phases.tls {
+        parts.push(format!("tls {}ms", tls.as_millis()));
+    }
+    parts.push(format!("ttfb {}ms", phases.ttfb.as_millis()));
+    parts.push(format!("download {}ms", phases.download.as_millis()));
+    if !phases.redirect.is_zero() {
+        parts.push(format!("redirect {}ms", phases.redirect.as_millis()));
+    }
+    parts.join(", ")
+}
 
-    let mut results = vec![];
-    for status in rx {
-        match &status.action_status {
-            Ok(code) => println!("[{}] {} => {}", status.timestamp.elapsed().unwrap().as_secs(), status.url, code),
+/// Summary of the attempts before the last one, such as ` after 2 retries (503, read_timeout)`.
+fn format_retries(status: &WebsiteStatus) -> String {
+    let Some((_, earlier)) = status.attempts.split_last() else {
+        return String::new();
+    };
+    if earlier.is_empty() {
+        return String::new();
+    }
+    let outcomes: Vec<String> = earlier
+        .iter()
+        .map(|attempt| match &attempt.outcome {
+            Ok(code) | Err(CheckError::UnexpectedStatus(code)) => code.to_string(),
+            Err(e) => e.kind().to_string(),
+        })
+        .collect();
+    let noun = if earlier.len() == 1 { "retry" } else { "retries" };
+    format!(" after {} {} ({})", earlier.len(), noun, outcomes.join(", "))
+}
+
+fn format_status(status: &WebsiteStatus) -> String {
+    format!("{}{}{}", format_outcome(status), format_retries(status), format_referrer(status))
+}
+
+fn format_referrer(status: &WebsiteStatus) -> String {
+    match &status.referrer {
+        Some(referrer) if !status.is_up() => format!(" (linked from {})", referrer),
+        _ => String::new(),
+    }
+}
+
+fn format_outcome(status: &WebsiteStatus) -> String {
+    match &status.action_status {
+        Ok(code) => match &status.phases {
+            Some(phases) => format!("[{}] {} => {} ({})", status.timestamp.elapsed().unwrap().as_secs(), status.url, code, format_phases(phases)),
+            None => format!("[{}] {} => {}", status.timestamp.elapsed().unwrap().as_secs(), status.url, code),
+        },
+        Err(CheckError::UnexpectedStatus(code)) => format!("[{}] {} => {} DOWN (unexpected status)", status.timestamp.elapsed().unwrap().as_secs(), status.url, code),
+        Err(CheckError::AssertionFailed(reason)) => format!("[{}] {} => FAIL: {}", status.timestamp.elapsed().unwrap().as_secs(), status.url, reason),
+        Err(e) => format!("[{}] {} => ERROR: {}", status.timestamp.elapsed().unwrap().as_secs(), status.url, e),
+    }
+}
+
+fn print_line(line: &str, to_stderr: bool) {
+    if to_stderr {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+fn open_ndjson(args: &Args) -> Option<NdjsonWriter> {
+    if !args.formats.contains(&Format::Ndjson) {
+        return None;
+    }
+    match NdjsonWriter::create(&args.ndjson_output) {
+        Ok(writer) => Some(writer),
+        Err(e) => {
+            eprintln!("failed to open {}: {}", args.ndjson_output, e);
+            std::process::exit(EXIT_INTERNAL);
+        }
+    }
+}
+
+fn stream(ndjson: &mut Option<NdjsonWriter>, status: &WebsiteStatus, path: &str) {
+    if let Some(writer) = ndjson
+        && let Err(e) = writer.write(status)
+    {
+        eprintln!("failed to write ndjson report to {}: {}", path, e);
+        std::process::exit(EXIT_INTERNAL);
+    }
+}
+
+fn format_transition(transition: &Transition) -> String {
+    match transition.downtime {
+        Some(downtime) => format!("[state] {} {} -> {} (down for {}s)", transition.url, transition.from, transition.to, downtime.as_secs()),
+        None => format!("[state] {} {} -> {}", transition.url, transition.from, transition.to),
+    }
+}
+
+fn main() {
+    let args = parse_args();
+
+    let mut builder = Checker::builder()
+        .engine(args.engine)
+        .workers(args.workers)
+        .concurrency(args.concurrency)
+        .timeout(Duration::from_secs(args.timeout))
+        .retry_policy(args.retry.clone())
+        .phase_timings(args.timings)
+        .per_host_delay(args.per_host_delay)
+        .limit_by_ip(args.per_ip)
+        .interval(Duration::from_secs(args.interval));
+    if let Some(max) = args.per_host {
+        builder = builder.per_host_concurrency(max);
+    }
+    if let Some(rate) = args.rate {
+        builder = builder.rate_limit(rate, args.burst);
+    }
+
+    let checker = match builder.build() {
+        Ok(checker) => checker,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(EXIT_INTERNAL);
+        }
+    };
+
+    let pages = load_sitemaps(&checker, &args);
+    let mut targets: Vec<Target> = args
+        .urls
+        .iter()
+        .chain(&pages)
+        .map(|url| {
+            let mut target = Target::new(url.as_str()).expect(args.expect.clone());
+            target.assertions = args.assertions.clone();
+            target
+        })
+        .collect();
+
+    let mut webhooks = vec![];
+    let mut modules = BTreeMap::new();
+    if let Some(path) = &args.config {
+        match Config::load(path) {
+            Ok(config) => {
+                targets.extend(config.targets);
+                webhooks = config.webhooks;
+                modules = config.modules;
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(EXIT_USAGE);
+            }
+        }
+    }
+
+    if args.serve {
+        let server = match checker.serve_probes(args.listen, modules) {
+            Ok(server) => server,
             Err(e) => {
-                let msg = e.split(':').next().unwrap_or("Unknown error").trim();
-                println!("[{}] {} => ERROR: {}", status.timestamp.elapsed().unwrap().as_secs(), status.url, msg);
-            }   
+                eprintln!("failed to start probe server: {}", e);
+                std::process::exit(EXIT_INTERNAL);
+            }
+        };
+        println!("Serving probes on http://{}/probe", server.local_addr());
+        loop {
+            std::thread::park();
+        }
+    }
+
+    if targets.is_empty() {
+        eprintln!("No targets to check");
+        std::process::exit(EXIT_USAGE);
+    }
 
+    if args.watch {
+        let sink = match WebhookSink::new(webhooks) {
+            Ok(sink) => sink,
+            Err(e) => {
+                eprintln!("failed to start webhook sink: {}", e);
+                std::process::exit(EXIT_INTERNAL);
+            }
+        };
+
+        let metrics = Arc::new(Metrics::new());
+        let _server = match args.metrics_listen.map(|addr| metrics.serve(addr)).transpose() {
+            Ok(server) => server,
+            Err(e) => {
+                eprintln!("failed to start metrics server: {}", e);
+                std::process::exit(EXIT_INTERNAL);
+            }
+        };
+
+        let mut ndjson = open_ndjson(&args);
+        for event in checker.watch(targets) {
+            match event {
+                WatchEvent::Checked(status) => {
+                    print_line(&format_status(&status), args.report_to_stdout());
+                    stream(&mut ndjson, &status, &args.ndjson_output);
+                    metrics.record(&status);
+                }
+                WatchEvent::Transition(transition) => {
+                    print_line(&format_transition(&transition), args.report_to_stdout());
+                    sink.notify(&transition);
+                }
+            }
         }
+        sink.flush();
+        return;
+    }
+
+    let mut ndjson = open_ndjson(&args);
+    let mut results = vec![];
+    let started = Instant::now();
+    let checked = match &args.crawl {
+        Some(crawl) => checker.crawl(targets.remove(0), crawl.clone()),
+        None => checker.check(targets),
+    };
+    for status in checked {
+        print_line(&format_status(&status), args.report_to_stdout());
+        stream(&mut ndjson, &status, &args.ndjson_output);
         results.push(status);
     }
+    let elapsed = started.elapsed();
+    let down = results.iter().filter(|status| !status.is_up()).count();
+    // Retries are requests too, and the rate limit counts every one of them.
+    let requests: usize = results.iter().map(|status| status.attempts.len()).sum();
+    let limit = match args.rate {
+        Some(rate) => format!(", limit {}/s with bursts of {}", rate, args.burst),
+        None => String::new(),
+    };
+    print_line(
+        &format!(
+            "Checked {} targets with {} requests in {:.2}s ({:.1} requests/s{}), {} down",
+            results.len(),
+            requests,
+            elapsed.as_secs_f64(),
+            requests as f64 / elapsed.as_secs_f64(),
+            limit,
+            down
+        ),
+        args.report_to_stdout(),
+    );
 
-    for handle in handles {
-        handle.join().unwrap();
+    for format in &args.formats {
+        let path = args.output_of(*format);
+        let written = match format {
+            Format::Json => write_json(&results, path),
+            Format::Csv => write_csv(&results, path),
+            Format::Junit => write_junit(&results, path),
+            // Already streamed while checking.
+            Format::Ndjson => continue,
+        };
+        if let Err(e) = written {
+            eprintln!("failed to write {} report to {}: {}", format, path, e);
+            std::process::exit(EXIT_INTERNAL);
+        }
     }
 
-    write_json(&results);
+    let ratio = (results.len() - down) as f64 / results.len() as f64;
+    if down > args.max_failures || args.min_success_ratio.is_some_and(|min| ratio < min) {
+        eprintln!("{} of {} checks down", down, results.len());
+        std::process::exit(EXIT_DOWN);
+    }
 }
@@ -0,0 +1,227 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use hyper::{header, Body, Response, StatusCode};
+
+use crate::{server, HttpServer, Phases, WebsiteStatus};
+
+/// Upper bounds of the response time histogram buckets, in seconds.
+const BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+struct Series {
+    up: bool,
+    status_code: Option<u16>,
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+    phases: Option<Phases>,
+    cert_expiry: Option<SystemTime>,
+    last_check: SystemTime,
+}
+
+/// Per-target gauges and histograms built from check results, rendered in
+/// the Prometheus text exposition format.
+///
+/// Every series is labelled with the target's `url`. Tags written as
+/// `key=value` become labels of their own; any other tags are joined into
+/// a single `tags` label.
+#[derive(Default)]
+pub struct Metrics {
+    series: Mutex<BTreeMap<String, Series>>,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    pub fn record(&self, status: &WebsiteStatus) {
+        let seconds = status.response_time.as_secs_f64();
+        let mut series = self.series.lock().unwrap();
+        let entry = series.entry(labels_of(status)).or_insert_with(|| Series {
+            up: false,
+            status_code: None,
+            buckets: [0; BUCKETS.len()],
+            sum: 0.0,
+            count: 0,
+            phases: None,
+            cert_expiry: None,
+            last_check: status.timestamp,
+        });
+
+        entry.up = status.is_up();
+        entry.status_code = status.status_code();
+        for (bucket, bound) in entry.buckets.iter_mut().zip(BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        entry.sum += seconds;
+        entry.count += 1;
+        if status.phases.is_some() {
+            entry.phases = status.phases.clone();
+        }
+        if status.cert_expiry.is_some() {
+            entry.cert_expiry = status.cert_expiry;
+        }
+        entry.last_check = status.timestamp;
+    }
+
+    pub fn render(&self) -> String {
+        let series = self.series.lock().unwrap();
+        let mut out = String::new();
+
+        family(&mut out, "status_checker_up", "gauge", "Whether the last check of the target succeeded.");
+        for (labels, entry) in series.iter() {
+            sample(&mut out, "status_checker_up", labels, "", u8::from(entry.up) as f64);
+        }
+
+        family(
+            &mut out,
+            "status_checker_status_code",
+            "gauge",
+            "HTTP status code of the last check, 0 if the target did not answer.",
+        );
+        for (labels, entry) in series.iter() {
+            sample(&mut out, "status_checker_status_code", labels, "", entry.status_code.unwrap_or(0) as f64);
+        }
+
+        family(&mut out, "status_checker_response_time_seconds", "histogram", "Response time of every check.");
+        for (labels, entry) in series.iter() {
+            for (count, bound) in entry.buckets.iter().zip(BUCKETS) {
+                let le = format!("le=\"{}\"", bound);
+                sample(&mut out, "status_checker_response_time_seconds_bucket", labels, &le, *count as f64);
+            }
+            sample(&mut out, "status_checker_response_time_seconds_bucket", labels, "le=\"+Inf\"", entry.count as f64);
+            sample(&mut out, "status_checker_response_time_seconds_sum", labels, "", entry.sum);
+            sample(&mut out, "status_checker_response_time_seconds_count", labels, "", entry.count as f64);
+        }
+
+        family(
+            &mut out,
+            "status_checker_phase_seconds",
+            "gauge",
+            "Time spent in each phase of the last traced check.",
+        );
+        for (labels, entry) in series.iter() {
+            let Some(phases) = &entry.phases else {
+                continue;
+            };
+            let mut timings = vec![("dns", phases.dns), ("connect", phases.connect)];
+            if let Some(tls) = phases.tls {
+                timings.push(("tls", tls));
+            }
+            timings.push(("ttfb", phases.ttfb));
+            timings.push(("download", phases.download));
+            timings.push(("redirect", phases.redirect));
+            for (phase, duration) in timings {
+                let phase = format!("phase=\"{}\"", phase);
+                sample(&mut out, "status_checker_phase_seconds", labels, &phase, duration.as_secs_f64());
+            }
+        }
+
+        family(
+            &mut out,
+            "status_checker_cert_expiry_timestamp_seconds",
+            "gauge",
+            "Unix time at which the target's TLS certificate expires.",
+        );
+        for (labels, entry) in series.iter() {
+            if let Some(expiry) = entry.cert_expiry {
+                sample(&mut out, "status_checker_cert_expiry_timestamp_seconds", labels, "", unix_secs(expiry));
+            }
+        }
+
+        family(
+            &mut out,
+            "status_checker_last_check_timestamp_seconds",
+            "gauge",
+            "Unix time of the last check of the target.",
+        );
+        for (labels, entry) in series.iter() {
+            sample(&mut out, "status_checker_last_check_timestamp_seconds", labels, "", unix_secs(entry.last_check));
+        }
+
+        out
+    }
+
+    /// Serve the metrics at `/metrics` on `addr` until the returned server is dropped.
+    pub fn serve(self: &Arc<Self>, addr: SocketAddr) -> io::Result<HttpServer> {
+        let metrics = Arc::clone(self);
+        server::serve(addr, move |req| {
+            let response = if req.uri().path() == "/metrics" {
+                Response::builder()
+                    .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+                    .body(Body::from(metrics.render()))
+            } else {
+                Response::builder().status(StatusCode::NOT_FOUND).body(Body::from("not found\n"))
+            };
+            let response = response.unwrap_or_default();
+            async move { response }
+        })
+    }
+}
+
+pub(crate) fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+pub(crate) fn sample(out: &mut String, name: &str, labels: &str, extra: &str, value: f64) {
+    let labels = match (labels.is_empty(), extra.is_empty()) {
+        (true, true) => String::new(),
+        (false, true) => format!("{{{}}}", labels),
+        (true, false) => format!("{{{}}}", extra),
+        (false, false) => format!("{{{},{}}}", labels, extra),
+    };
+    let _ = writeln!(out, "{}{} {}", name, labels, value);
+}
+
+pub(crate) fn unix_secs(time: SystemTime) -> f64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs_f64()
+}
+
+/// The label set identifying the target that produced `status`.
+fn labels_of(status: &WebsiteStatus) -> String {
+    let mut labels = BTreeMap::new();
+    let mut plain = vec![];
+    for tag in &status.tags {
+        match tag.split_once('=') {
+            Some((key, value)) if is_label_name(key) => {
+                labels.insert(key.to_string(), value.to_string());
+            }
+            _ => plain.push(tag.as_str()),
+        }
+    }
+    if !plain.is_empty() {
+        labels.insert("tags".to_string(), plain.join(","));
+    }
+
+    let mut out = format!("url=\"{}\"", escape(&status.url));
+    for (key, value) in labels {
+        let _ = write!(out, ",{}=\"{}\"", key, escape(&value));
+    }
+    out
+}
+
+/// Whether `name` can be used as a tag label without clashing with the
+/// labels the exporter sets itself.
+fn is_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.starts_with("__")
+        && !matches!(name, "url" | "tags" | "le" | "phase")
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -0,0 +1,433 @@
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+    process,
+    str::FromStr,
+    time::SystemTime,
+};
+
+use serde::{Serialize, Serializer};
+
+use crate::{CheckError, Phases, WebsiteStatus};
+
+/// A report format written at the end of a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Csv,
+    Junit,
+    Ndjson,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "junit" => Ok(Format::Junit),
+            "ndjson" => Ok(Format::Ndjson),
+            other => Err(format!("unknown format '{}', expected 'json', 'csv', 'junit' or 'ndjson'", other)),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Json => write!(f, "json"),
+            Format::Csv => write!(f, "csv"),
+            Format::Junit => write!(f, "junit"),
+            Format::Ndjson => write!(f, "ndjson"),
+        }
+    }
+}
+
+/// Version of the JSON report layout, bumped whenever a field changes meaning
+/// or is removed.
+pub const SCHEMA_VERSION: u32 = 2;
+
+#[derive(Serialize)]
+struct Report<'a> {
+    schema_version: u32,
+    results: &'a [WebsiteStatus],
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    url: &'a str,
+    up: bool,
+    action_status: ActionStatus<'a>,
+    response_time_ms: u64,
+    total_time_ms: u64,
+    attempts: Vec<AttemptRecord<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    phases_ms: Option<PhasesMs>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cert_expiry: Option<u64>,
+    tags: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    referrer: Option<&'a str>,
+    timestamp: u64,
+}
+
+#[derive(Serialize)]
+enum ActionStatus<'a> {
+    Ok(u16),
+    Err { kind: &'a str, message: String },
+}
+
+impl<'a> From<&'a Result<u16, CheckError>> for ActionStatus<'a> {
+    fn from(outcome: &'a Result<u16, CheckError>) -> Self {
+        match outcome {
+            Ok(code) => ActionStatus::Ok(*code),
+            Err(e) => ActionStatus::Err {
+                kind: e.kind(),
+                message: e.to_string(),
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct AttemptRecord<'a> {
+    action_status: ActionStatus<'a>,
+    duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delay_ms: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct PhasesMs {
+    dns: u64,
+    connect: u64,
+    tls: Option<u64>,
+    ttfb: u64,
+    download: u64,
+    redirect: u64,
+}
+
+impl From<&Phases> for PhasesMs {
+    fn from(phases: &Phases) -> Self {
+        PhasesMs {
+            dns: phases.dns.as_millis() as u64,
+            connect: phases.connect.as_millis() as u64,
+            tls: phases.tls.map(|tls| tls.as_millis() as u64),
+            ttfb: phases.ttfb.as_millis() as u64,
+            download: phases.download.as_millis() as u64,
+            redirect: phases.redirect.as_millis() as u64,
+        }
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+impl Serialize for WebsiteStatus {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let attempts = self
+            .attempts
+            .iter()
+            .map(|attempt| AttemptRecord {
+                action_status: ActionStatus::from(&attempt.outcome),
+                duration_ms: attempt.duration.as_millis() as u64,
+                delay_ms: attempt.delay.map(|delay| delay.as_millis() as u64),
+            })
+            .collect();
+
+        Record {
+            url: &self.url,
+            up: self.is_up(),
+            action_status: ActionStatus::from(&self.action_status),
+            response_time_ms: self.response_time.as_millis() as u64,
+            total_time_ms: self.total_time.as_millis() as u64,
+            attempts,
+            phases_ms: self.phases.as_ref().map(PhasesMs::from),
+            cert_expiry: self.cert_expiry.map(unix_secs),
+            tags: &self.tags,
+            referrer: self.referrer.as_deref(),
+            timestamp: unix_secs(self.timestamp),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Write `results` as a JSON report to `path`, or to stdout when `path` is
+/// `-`. Files are replaced atomically, so readers never see a partial report.
+pub fn write_json(results: &[WebsiteStatus], path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+    let report = Report {
+        schema_version: SCHEMA_VERSION,
+        results,
+    };
+
+    if path == Path::new("-") {
+        let mut out = io::stdout().lock();
+        serde_json::to_writer_pretty(&mut out, &report)?;
+        writeln!(out)?;
+        return out.flush();
+    }
+
+    write_atomically(path, |out| {
+        serde_json::to_writer_pretty(&mut *out, &report)?;
+        writeln!(out)
+    })
+}
+
+/// Streams results as newline-delimited JSON, one object per line in the
+/// same shape as the entries of the JSON report. Every line is flushed as
+/// soon as it is written, so the output can be tailed and survives an
+/// interrupted run.
+pub struct NdjsonWriter {
+    out: Box<dyn Write + Send>,
+}
+
+impl NdjsonWriter {
+    /// Create (or truncate) `path`, or write to stdout when `path` is `-`.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<NdjsonWriter> {
+        let path = path.as_ref();
+        let out: Box<dyn Write + Send> = if path == Path::new("-") {
+            Box::new(io::stdout())
+        } else {
+            Box::new(BufWriter::new(File::create(path)?))
+        };
+        Ok(NdjsonWriter { out })
+    }
+
+    pub fn from_writer(out: impl Write + Send + 'static) -> NdjsonWriter {
+        NdjsonWriter { out: Box::new(out) }
+    }
+
+    pub fn write(&mut self, status: &WebsiteStatus) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, status)?;
+        writeln!(self.out)?;
+        self.out.flush()
+    }
+}
+
+#[derive(Serialize)]
+struct Row<'a> {
+    url: &'a str,
+    outcome: &'a str,
+    status_code: Option<u16>,
+    error_kind: Option<&'a str>,
+    response_time_ms: u64,
+    timestamp: String,
+    tags: String,
+    referrer: Option<&'a str>,
+}
+
+/// Write `results` as CSV to `path`, or to stdout when `path` is `-`, one row
+/// per result. Tags are joined with `;` into a single column, and `referrer`
+/// is only filled in for links found while crawling.
+pub fn write_csv(results: &[WebsiteStatus], path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+    let write_rows = |out: &mut dyn Write| -> io::Result<()> {
+        // Headers are written by hand so that an empty run still has them.
+        let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(out);
+        writer.write_record(["url", "outcome", "status_code", "error_kind", "response_time_ms", "timestamp", "tags", "referrer"])?;
+        for result in results {
+            writer.serialize(Row {
+                url: &result.url,
+                outcome: if result.is_up() { "up" } else { "down" },
+                status_code: result.status_code(),
+                error_kind: result.action_status.as_ref().err().map(|e| e.kind()),
+                response_time_ms: result.response_time.as_millis() as u64,
+                timestamp: humantime::format_rfc3339_seconds(result.timestamp).to_string(),
+                tags: result.tags.join(";"),
+                referrer: result.referrer.as_deref(),
+            })?;
+        }
+        writer.flush()
+    };
+
+    if path == Path::new("-") {
+        let mut out = io::stdout().lock();
+        write_rows(&mut out)?;
+        return out.flush();
+    }
+
+    write_atomically(path, |out| write_rows(out))
+}
+
+/// Write `results` as a JUnit XML report to `path`, or to stdout when `path`
+/// is `-`. Every result is a testcase named after its URL, and down results
+/// carry a failure with the error kind and message.
+pub fn write_junit(results: &[WebsiteStatus], path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+    let failures = results.iter().filter(|result| !result.is_up()).count();
+    let time: f64 = results.iter().map(|result| result.response_time.as_secs_f64()).sum();
+    let timestamp = results
+        .iter()
+        .map(|result| result.timestamp)
+        .min()
+        .unwrap_or_else(SystemTime::now);
+
+    let write_report = |out: &mut dyn Write| -> io::Result<()> {
+        writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(
+            out,
+            "<testsuites name=\"status_checker\" tests=\"{}\" failures=\"{}\" errors=\"0\" time=\"{:.3}\">",
+            results.len(),
+            failures,
+            time
+        )?;
+        writeln!(
+            out,
+            "  <testsuite name=\"status_checker\" tests=\"{}\" failures=\"{}\" errors=\"0\" skipped=\"0\" time=\"{:.3}\" timestamp=\"{}\">",
+            results.len(),
+            failures,
+            time,
+            humantime::format_rfc3339_seconds(timestamp)
+        )?;
+        for result in results {
+            let name = xml_escape(&result.url);
+            let time = result.response_time.as_secs_f64();
+            match &result.action_status {
+                Ok(_) => writeln!(out, "    <testcase name=\"{}\" classname=\"status_checker\" time=\"{:.3}\"/>", name, time)?,
+                Err(e) => {
+                    let message = xml_escape(&e.to_string());
+                    let details = match &result.referrer {
+                        Some(referrer) => format!("{} (linked from {})", message, xml_escape(referrer)),
+                        None => message.clone(),
+                    };
+                    writeln!(out, "    <testcase name=\"{}\" classname=\"status_checker\" time=\"{:.3}\">", name, time)?;
+                    writeln!(out, "      <failure type=\"{}\" message=\"{}\">{}</failure>", e.kind(), message, details)?;
+                    writeln!(out, "    </testcase>")?;
+                }
+            }
+        }
+        writeln!(out, "  </testsuite>")?;
+        writeln!(out, "</testsuites>")
+    };
+
+    if path == Path::new("-") {
+        let mut out = io::stdout().lock();
+        write_report(&mut out)?;
+        return out.flush();
+    }
+
+    write_atomically(path, |out| write_report(out))
+}
+
+/// Escape `text` for use in XML attributes and text, dropping the control
+/// characters XML 1.0 cannot represent at all.
+fn xml_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            '\n' => out.push_str("&#10;"),
+            '\t' | '\r' => out.push(c),
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Write to a temporary file next to `path` and rename it into place once
+/// everything has been written and synced.
+pub(crate) fn write_atomically(path: &Path, write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>) -> io::Result<()> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "output path has no file name"))?;
+    let tmp = path.with_file_name(format!(".{}.{}.tmp", name.to_string_lossy(), process::id()));
+
+    let result = File::create(&tmp).and_then(|file| {
+        let mut out = BufWriter::new(file);
+        write(&mut out)?;
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&tmp, path)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs, io,
+        path::PathBuf,
+        process,
+        time::{Duration, SystemTime},
+    };
+
+    use serde_json::Value;
+
+    use super::{write_atomically, write_json};
+    use crate::{Attempt, CheckError, WebsiteStatus};
+
+    /// A fresh directory under the system temp dir, unique to this process and `name`.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("status_checker-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn escapes_quotes_and_backslashes() {
+        let url = r#"https://example.com/a"b\c"#;
+        let error = CheckError::AssertionFailed(r#"body does not contain '"x\y'"#.to_string());
+        let message = error.to_string();
+        let status = WebsiteStatus {
+            url: url.to_string(),
+            action_status: Err(error.clone()),
+            response_time: Duration::from_millis(12),
+            total_time: Duration::from_millis(12),
+            phases: None,
+            cert_expiry: None,
+            tags: vec![r#"tag "with" \quotes"#.to_string()],
+            referrer: None,
+            attempts: vec![Attempt {
+                outcome: Err(error),
+                duration: Duration::from_millis(12),
+                delay: None,
+            }],
+            timestamp: SystemTime::now(),
+        };
+
+        let line: Value = serde_json::from_str(&serde_json::to_string(&status).unwrap()).unwrap();
+        assert_eq!(line["url"], url);
+        assert_eq!(line["action_status"]["Err"]["message"], message.as_str());
+        assert_eq!(line["attempts"][0]["action_status"]["Err"]["message"], message.as_str());
+        assert_eq!(line["tags"][0], r#"tag "with" \quotes"#);
+
+        let dir = scratch_dir("json");
+        let path = dir.join("report.json");
+        write_json(&[status], &path).unwrap();
+        let report: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(report["results"][0]["url"], url);
+        assert_eq!(report["results"][0]["action_status"]["Err"]["message"], message.as_str());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_write_leaves_no_temp_file() {
+        let dir = scratch_dir("atomic");
+        let path = dir.join("report.json");
+        fs::write(&path, "previous report").unwrap();
+
+        let result = write_atomically(&path, |out| {
+            io::Write::write_all(out, b"partial")?;
+            Err(io::Error::other("disk full"))
+        });
+        assert_eq!(result.unwrap_err().to_string(), "disk full");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "previous report");
+        let left: Vec<_> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        assert_eq!(left, ["report.json"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,246 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use hyper::{header, Body, Request, Response, StatusCode};
+use reqwest::{header::HeaderMap, Method};
+use tokio::runtime::Handle;
+
+use crate::{
+    fetch_status,
+    metrics::{family, sample, unix_secs},
+    target::has_scheme,
+    Assertion, CheckError, Checker, ExpectedStatus, HttpServer, RetryPolicy, Target, Transport, WebsiteStatus,
+};
+
+/// Module used when a probe does not name one, as in blackbox_exporter.
+pub const DEFAULT_MODULE: &str = "http_2xx";
+
+/// How a target passed to `/probe` is requested and what counts as success,
+/// selected with the `module` query parameter.
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub method: Method,
+    pub headers: HeaderMap,
+    pub body: Option<String>,
+    pub timeout: Option<Duration>,
+    pub retries: Option<u32>,
+    pub expect: ExpectedStatus,
+    pub assertions: Vec<Assertion>,
+}
+
+impl Default for Module {
+    fn default() -> Self {
+        Module {
+            method: Method::GET,
+            headers: HeaderMap::new(),
+            body: None,
+            timeout: None,
+            retries: None,
+            expect: ExpectedStatus::success(),
+            assertions: vec![],
+        }
+    }
+}
+
+impl Module {
+    pub fn target(&self, url: impl Into<String>) -> Target {
+        let mut target = Target::new(url).expect(self.expect.clone());
+        target.method = self.method.clone();
+        target.headers = self.headers.clone();
+        target.body = self.body.clone();
+        target.timeout = self.timeout;
+        target.retries = self.retries;
+        target.assertions = self.assertions.clone();
+        target
+    }
+}
+
+struct Prober {
+    transport: Transport,
+    runtime: Handle,
+    retry: RetryPolicy,
+    modules: BTreeMap<String, Module>,
+}
+
+impl Checker {
+    /// Serve blackbox_exporter-style probes at `/probe?target=URL&module=NAME`
+    /// until the returned server is dropped. A default `http_2xx` module is
+    /// added unless `modules` defines one.
+    pub fn serve_probes(&self, addr: SocketAddr, mut modules: BTreeMap<String, Module>) -> io::Result<HttpServer> {
+        modules.entry(DEFAULT_MODULE.to_string()).or_default();
+        let prober = Arc::new(Prober {
+            transport: self.transport.clone(),
+            runtime: self.runtime.handle().clone(),
+            retry: self.retry.clone(),
+            modules,
+        });
+
+        crate::server::serve(addr, move |req| {
+            let prober = Arc::clone(&prober);
+            async move {
+                if req.uri().path() != "/probe" {
+                    return text(StatusCode::NOT_FOUND, "not found\n".to_string());
+                }
+                match prober.probe(req).await {
+                    Ok(metrics) => text(StatusCode::OK, metrics),
+                    Err((status, message)) => text(status, format!("{}\n", message)),
+                }
+            }
+        })
+    }
+}
+
+impl Prober {
+    async fn probe(&self, req: Request<Body>) -> Result<String, (StatusCode, String)> {
+        let mut target = None;
+        let mut module = DEFAULT_MODULE.to_string();
+        for (key, value) in url::form_urlencoded::parse(req.uri().query().unwrap_or("").as_bytes()) {
+            match key.as_ref() {
+                "target" => target = Some(value.into_owned()),
+                "module" => module = value.into_owned(),
+                _ => {}
+            }
+        }
+
+        let Some(url) = target.filter(|url| !url.is_empty()) else {
+            return Err((StatusCode::BAD_REQUEST, "Target parameter is missing".to_string()));
+        };
+        let Some(module) = self.modules.get(&module) else {
+            return Err((StatusCode::BAD_REQUEST, format!("Unknown module '{}'", module)));
+        };
+
+        let url = if has_scheme(&url) { url } else { format!("http://{}", url) };
+        let mut target = module.target(url);
+        // Like blackbox_exporter, finish before Prometheus gives up on the scrape.
+        if target.timeout.is_none() {
+            target.timeout = scrape_timeout(&req);
+        }
+
+        let transport = self.transport.clone();
+        let retry = self.retry.clone();
+        let started = Instant::now();
+        let status = self
+            .runtime
+            .spawn(async move { fetch_status(&transport, &target, &retry).await })
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("probe failed: {}", e)))?;
+
+        Ok(render(&status, started.elapsed(), !module.assertions.is_empty()))
+    }
+}
+
+fn scrape_timeout(req: &Request<Body>) -> Option<Duration> {
+    let secs: f64 = req
+        .headers()
+        .get("X-Prometheus-Scrape-Timeout-Seconds")?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()?;
+    Duration::try_from_secs_f64(secs - 0.5).ok().filter(|timeout| !timeout.is_zero())
+}
+
+fn text(status: StatusCode, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(Body::from(body))
+        .unwrap_or_default()
+}
+
+/// The probe result as the metrics blackbox_exporter's http prober reports.
+fn render(status: &WebsiteStatus, duration: Duration, has_assertions: bool) -> String {
+    let mut out = String::new();
+
+    family(&mut out, "probe_success", "gauge", "Displays whether or not the probe was a success");
+    sample(&mut out, "probe_success", "", "", u8::from(status.is_up()) as f64);
+
+    family(&mut out, "probe_duration_seconds", "gauge", "Returns how long the probe took to complete in seconds");
+    sample(&mut out, "probe_duration_seconds", "", "", duration.as_secs_f64());
+
+    family(&mut out, "probe_http_status_code", "gauge", "Response HTTP status code");
+    sample(&mut out, "probe_http_status_code", "", "", status.status_code().unwrap_or(0) as f64);
+
+    if let Some(phases) = &status.phases {
+        family(&mut out, "probe_dns_lookup_time_seconds", "gauge", "Returns the time taken for probe dns lookup in seconds");
+        sample(&mut out, "probe_dns_lookup_time_seconds", "", "", phases.dns.as_secs_f64());
+
+        family(&mut out, "probe_http_duration_seconds", "gauge", "Duration of http request by phase");
+        let timings = [
+            ("resolve", phases.dns),
+            ("connect", phases.connect),
+            ("tls", phases.tls.unwrap_or_default()),
+            ("processing", phases.ttfb),
+            ("transfer", phases.download),
+        ];
+        for (phase, duration) in timings {
+            let phase = format!("phase=\"{}\"", phase);
+            sample(&mut out, "probe_http_duration_seconds", "", &phase, duration.as_secs_f64());
+        }
+    }
+
+    if status.url.starts_with("https://") || status.cert_expiry.is_some() {
+        family(&mut out, "probe_http_ssl", "gauge", "Indicates if SSL was used for the final redirect");
+        sample(&mut out, "probe_http_ssl", "", "", u8::from(status.cert_expiry.is_some()) as f64);
+    }
+
+    if let Some(expiry) = status.cert_expiry {
+        family(&mut out, "probe_ssl_earliest_cert_expiry", "gauge", "Returns last SSL chain expiry in unixtime");
+        sample(&mut out, "probe_ssl_earliest_cert_expiry", "", "", unix_secs(expiry));
+    }
+
+    if has_assertions {
+        let failed = matches!(status.action_status, Err(CheckError::AssertionFailed(_)));
+        family(&mut out, "probe_failed_due_to_regex", "gauge", "Indicates if probe failed due to regex");
+        sample(&mut out, "probe_failed_due_to_regex", "", "", u8::from(failed) as f64);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, net::SocketAddr};
+
+    use hyper::{Body, Response, StatusCode};
+
+    use crate::{server, Checker};
+
+    fn probe(prober: SocketAddr, target: &str) -> String {
+        let query: String = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("target", target)
+            .finish();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let response = reqwest::get(format!("http://{}/probe?{}", prober, query)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            response.text().await.unwrap()
+        })
+    }
+
+    #[test]
+    fn targets_without_scheme_get_http() {
+        let site = server::serve(SocketAddr::from(([127, 0, 0, 1], 0)), |req| {
+            let status = if req.uri().path() == "/ok" { StatusCode::OK } else { StatusCode::NOT_FOUND };
+            async move { Response::builder().status(status).body(Body::empty()).unwrap() }
+        })
+        .unwrap();
+        let checker = Checker::builder().build().unwrap();
+        let prober = checker.serve_probes(SocketAddr::from(([127, 0, 0, 1], 0)), BTreeMap::new()).unwrap();
+
+        for target in [
+            format!("{}/ok", site.local_addr()),
+            format!("{}/ok?next=https://example.com/", site.local_addr()),
+            format!("http://{}/ok", site.local_addr()),
+        ] {
+            let metrics = probe(prober.local_addr(), &target);
+            assert!(metrics.contains("probe_success 1"), "{}:\n{}", target, metrics);
+        }
+        assert!(probe(prober.local_addr(), &format!("{}/missing", site.local_addr())).contains("probe_success 0"));
+    }
+}
@@ -0,0 +1,332 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::{runtime::Handle, sync::Notify};
+use url::Url;
+
+use crate::Target;
+
+/// How hard a single host may be hit while checking a list.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct HostLimits {
+    /// Most checks in flight against one host at a time.
+    pub(crate) max_in_flight: Option<usize>,
+    /// Least time between two requests to one host, retries included.
+    pub(crate) delay: Duration,
+    /// Group targets by the address their host resolves to instead of by name.
+    pub(crate) by_ip: bool,
+}
+
+/// A target taken from the queue, to be handed back to `finish` once checked.
+pub(crate) struct Job {
+    pub(crate) target: Target,
+    /// The number the target was pushed with.
+    pub(crate) index: usize,
+    key: String,
+}
+
+struct Host {
+    queue: VecDeque<(usize, Target)>,
+    in_flight: usize,
+    next_start: Instant,
+}
+
+#[derive(Default)]
+struct State {
+    hosts: HashMap<String, Host>,
+    /// Hosts with queued targets, in the order they were first seen.
+    waiting: VecDeque<String>,
+    in_flight: usize,
+    resolved: HashMap<String, String>,
+    /// Targets held back while their host name is looked up, by host name.
+    resolving: HashMap<String, Vec<(usize, Target)>>,
+    /// More targets may still be pushed from outside, as in watch mode.
+    open: bool,
+}
+
+enum Poll {
+    Ready(Box<Job>),
+    Wait(Option<Instant>),
+    Done,
+}
+
+/// Targets waiting to be checked, grouped by host. A host at its limits is
+/// skipped rather than waited on, so workers stay busy with other hosts.
+/// The queue is finished once it is empty and no taken job is outstanding,
+/// unless it was opened to be fed until `close` is called.
+pub(crate) struct JobQueue {
+    limits: HostLimits,
+    /// Runs the host name lookups of `limits.by_ip`.
+    runtime: Handle,
+    state: Mutex<State>,
+    ready: Condvar,
+    notify: Notify,
+}
+
+impl JobQueue {
+    pub(crate) fn new(limits: HostLimits, runtime: Handle) -> JobQueue {
+        JobQueue {
+            limits,
+            runtime,
+            state: Mutex::new(State::default()),
+            ready: Condvar::new(),
+            notify: Notify::new(),
+        }
+    }
+
+    /// A queue that stays open while empty, for targets pushed as they fall due.
+    pub(crate) fn open(limits: HostLimits, runtime: Handle) -> JobQueue {
+        let queue = JobQueue::new(limits, runtime);
+        queue.state.lock().unwrap().open = true;
+        queue
+    }
+
+    /// Drop every queued target and let the queue finish once the jobs
+    /// already taken are done.
+    pub(crate) fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.open = false;
+        state.waiting.clear();
+        state.resolving.clear();
+        for host in state.hosts.values_mut() {
+            host.queue.clear();
+        }
+        drop(state);
+        self.wake();
+    }
+
+    /// Queue a target. When limiting by address, a target whose host has not
+    /// been resolved yet is held back until the lookup is done, without
+    /// blocking the caller.
+    pub(crate) fn push(self: &Arc<Self>, index: usize, target: Target) {
+        let mut state = self.state.lock().unwrap();
+        let key = match host_of(&target.url) {
+            Some((host, port)) if self.limits.by_ip => match state.resolved.get(&host) {
+                Some(ip) => ip.clone(),
+                None => {
+                    let held = state.resolving.entry(host.clone()).or_default();
+                    held.push((index, target));
+                    // Later targets on the host wait for the lookup already running.
+                    if held.len() == 1 {
+                        self.resolve(host, port);
+                    }
+                    return;
+                }
+            },
+            Some((host, _)) => host,
+            None => target.url.clone(),
+        };
+        enqueue(&mut state, key, index, target);
+        drop(state);
+        self.wake();
+    }
+
+    fn resolve(self: &Arc<Self>, host: String, port: u16) {
+        let queue = Arc::clone(self);
+        self.runtime.spawn(async move {
+            // Hosts are resolved once; those that do not resolve are limited by name.
+            let ip = tokio::net::lookup_host((host.as_str(), port))
+                .await
+                .ok()
+                .and_then(|mut addrs| addrs.next())
+                .map_or_else(|| host.clone(), |addr| addr.ip().to_string());
+
+            let mut state = queue.state.lock().unwrap();
+            // Nothing is held back any more if the queue was closed meanwhile.
+            let held = state.resolving.remove(&host).unwrap_or_default();
+            state.resolved.insert(host, ip.clone());
+            for (index, target) in held {
+                enqueue(&mut state, ip.clone(), index, target);
+            }
+            drop(state);
+            queue.wake();
+        });
+    }
+
+    /// Take the next target, blocking until one of its host's limits allow it.
+    pub(crate) fn next_blocking(&self) -> Option<Job> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            state = match self.poll(&mut state) {
+                Poll::Ready(job) => return Some(*job),
+                Poll::Done => return None,
+                Poll::Wait(Some(until)) => {
+                    let wait = until.saturating_duration_since(Instant::now());
+                    self.ready.wait_timeout(state, wait).unwrap().0
+                }
+                Poll::Wait(None) => self.ready.wait(state).unwrap(),
+            };
+        }
+    }
+
+    /// Take the next target, waiting until one of its host's limits allow it.
+    pub(crate) async fn next(&self) -> Option<Job> {
+        loop {
+            // Registered before polling so that a wake-up in between is not lost.
+            let notified = self.notify.notified();
+            let poll = self.poll(&mut self.state.lock().unwrap());
+            match poll {
+                Poll::Ready(job) => return Some(*job),
+                Poll::Done => return None,
+                Poll::Wait(Some(until)) => {
+                    let _ = tokio::time::timeout_at(until.into(), notified).await;
+                }
+                Poll::Wait(None) => notified.await,
+            }
+        }
+    }
+
+    /// When a retry of `job` may start, no sooner than `earliest`. The slot is
+    /// taken from the host's delay, so other targets on the host wait for it too.
+    pub(crate) fn retry_at(&self, job: &Job, earliest: Instant) -> Instant {
+        let mut state = self.state.lock().unwrap();
+        let Some(host) = state.hosts.get_mut(&job.key) else {
+            return earliest;
+        };
+        let at = earliest.max(host.next_start);
+        host.next_start = at + self.limits.delay;
+        at
+    }
+
+    pub(crate) fn finish(&self, job: &Job) {
+        let mut state = self.state.lock().unwrap();
+        state.in_flight -= 1;
+        if let Some(host) = state.hosts.get_mut(&job.key) {
+            host.in_flight -= 1;
+        }
+        drop(state);
+        self.wake();
+    }
+
+    fn wake(&self) {
+        self.ready.notify_all();
+        self.notify.notify_waiters();
+    }
+
+    fn poll(&self, state: &mut State) -> Poll {
+        let now = Instant::now();
+        let mut earliest: Option<Instant> = None;
+
+        for position in 0..state.waiting.len() {
+            let key = &state.waiting[position];
+            let host = state.hosts.get_mut(key).unwrap();
+            if self.limits.max_in_flight.is_some_and(|max| host.in_flight >= max) {
+                continue;
+            }
+            if host.next_start > now {
+                earliest = Some(earliest.map_or(host.next_start, |at| at.min(host.next_start)));
+                continue;
+            }
+
+            let (index, target) = host.queue.pop_front().unwrap();
+            host.in_flight += 1;
+            host.next_start = now + self.limits.delay;
+            let job = Job {
+                target,
+                index,
+                key: key.clone(),
+            };
+            if host.queue.is_empty() {
+                state.waiting.remove(position);
+            }
+            state.in_flight += 1;
+            return Poll::Ready(Box::new(job));
+        }
+
+        if !state.open && state.waiting.is_empty() && state.resolving.is_empty() && state.in_flight == 0 {
+            Poll::Done
+        } else {
+            Poll::Wait(earliest)
+        }
+    }
+}
+
+fn enqueue(state: &mut State, key: String, index: usize, target: Target) {
+    let host = state.hosts.entry(key.clone()).or_insert_with(|| Host {
+        queue: VecDeque::new(),
+        in_flight: 0,
+        next_start: Instant::now(),
+    });
+    host.queue.push_back((index, target));
+    if host.queue.len() == 1 {
+        state.waiting.push_back(key);
+    }
+}
+
+/// The host name and port a URL is checked at, if it has a host.
+fn host_of(url: &str) -> Option<(String, u16)> {
+    let parsed = Url::parse(url).ok()?;
+    let host = parsed.host_str()?.to_string();
+    Some((host, parsed.port_or_known_default().unwrap_or(80)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use tokio::runtime::Runtime;
+
+    use super::{HostLimits, JobQueue};
+    use crate::Target;
+
+    #[test]
+    fn targets_held_for_lookup_are_queued_by_address() {
+        let runtime = Runtime::new().unwrap();
+        let limits = HostLimits {
+            max_in_flight: Some(1),
+            by_ip: true,
+            ..HostLimits::default()
+        };
+        let queue = Arc::new(JobQueue::new(limits, runtime.handle().clone()));
+        queue.push(0, Target::new("http://127.0.0.1:1/a"));
+        queue.push(1, Target::new("http://127.0.0.1:2/b"));
+        queue.push(2, Target::new("http://127.0.0.2:1/c"));
+
+        let first = queue.next_blocking().unwrap();
+        let second = queue.next_blocking().unwrap();
+        // Both ports of 127.0.0.1 share one address, so only one of them is in
+        // flight. The two lookups may finish in either order.
+        let mut taken = [first.index, second.index];
+        taken.sort();
+        assert_eq!(taken, [0, 2]);
+        queue.finish(&first);
+        queue.finish(&second);
+        let third = queue.next_blocking().unwrap();
+        assert_eq!(third.index, 1);
+        queue.finish(&third);
+        assert!(queue.next_blocking().is_none());
+    }
+
+    #[test]
+    fn retries_keep_to_the_host_delay() {
+        let runtime = Runtime::new().unwrap();
+        let limits = HostLimits {
+            delay: Duration::from_millis(200),
+            ..HostLimits::default()
+        };
+        let queue = Arc::new(JobQueue::new(limits, runtime.handle().clone()));
+        queue.push(0, Target::new("http://127.0.0.1:1/a"));
+        queue.push(1, Target::new("http://127.0.0.1:1/b"));
+
+        let job = queue.next_blocking().unwrap();
+        let taken = Instant::now();
+        // A short backoff waits for the host's delay instead.
+        let retry = queue.retry_at(&job, taken + Duration::from_millis(50));
+        assert!(retry >= taken + Duration::from_millis(150), "{:?}", retry - taken);
+        // A long backoff is kept as it is.
+        let later = retry + Duration::from_millis(400);
+        assert_eq!(queue.retry_at(&job, later), later);
+
+        // The next target on the host waits for the slots taken by the retries.
+        queue.finish(&job);
+        let next = queue.next_blocking().unwrap();
+        assert!(Instant::now() >= later + Duration::from_millis(200));
+        queue.finish(&next);
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// The slowest rate a limit may be set to, one request every 1000 seconds.
+/// Slower rates would put waits beyond what a `Duration` holds.
+pub const MIN_RATE: f64 = 0.001;
+
+/// A token bucket refilled at `rate` tokens per second that holds at most
+/// `burst` tokens.
+#[derive(Debug, Clone)]
+pub(crate) struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket, so the first `burst` requests start right away.
+    pub(crate) fn new(rate: f64, burst: u32) -> TokenBucket {
+        let burst = f64::from(burst.max(1));
+        TokenBucket {
+            rate,
+            burst,
+            tokens: burst,
+            refilled: Instant::now(),
+        }
+    }
+
+    /// Take the next token and return when it is due. Tokens are handed out
+    /// in the order they are asked for, so the bucket may be overdrawn by
+    /// waiting callers and none of them starves.
+    pub(crate) fn reserve(&mut self, now: Instant) -> Instant {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst) - 1.0;
+        self.refilled = now;
+
+        if self.tokens >= 0.0 {
+            now
+        } else {
+            now + Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+/// A token bucket shared by every worker of a checker. A token is taken
+/// before each request attempt, so retries count against the rate too.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    bucket: Mutex<TokenBucket>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(rate: f64, burst: u32) -> RateLimiter {
+        RateLimiter {
+            bucket: Mutex::new(TokenBucket::new(rate, burst)),
+        }
+    }
+
+    /// Wait for the next token.
+    pub(crate) async fn acquire(&self) {
+        let due = self.bucket.lock().unwrap().reserve(Instant::now());
+        tokio::time::sleep_until(due.into()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{TokenBucket, MIN_RATE};
+
+    #[test]
+    fn burst_starts_right_away() {
+        let mut bucket = TokenBucket::new(2.0, 3);
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(bucket.reserve(now), now);
+        }
+        assert_eq!(bucket.reserve(now), now + Duration::from_millis(500));
+    }
+
+    #[test]
+    fn waiting_callers_are_spaced_by_the_rate() {
+        let mut bucket = TokenBucket::new(4.0, 1);
+        let now = Instant::now();
+        assert_eq!(bucket.reserve(now), now);
+        assert_eq!(bucket.reserve(now), now + Duration::from_millis(250));
+        assert_eq!(bucket.reserve(now), now + Duration::from_millis(500));
+        // A caller arriving later still queues behind the reservations ahead of it.
+        assert_eq!(bucket.reserve(now + Duration::from_millis(100)), now + Duration::from_millis(750));
+    }
+
+    #[test]
+    fn slowest_rate_keeps_waits_representable() {
+        let mut bucket = TokenBucket::new(MIN_RATE, 1);
+        let now = Instant::now();
+        for _ in 0..10_000 {
+            bucket.reserve(now);
+        }
+        assert_eq!(bucket.reserve(now), now + Duration::from_secs(10_000_000));
+    }
+
+    #[test]
+    fn refills_up_to_burst() {
+        let mut bucket = TokenBucket::new(10.0, 2);
+        let now = Instant::now();
+        bucket.reserve(now);
+        bucket.reserve(now);
+
+        // Idle for much longer than a refill takes: only `burst` tokens are kept.
+        let later = now + Duration::from_secs(5);
+        assert_eq!(bucket.reserve(later), later);
+        assert_eq!(bucket.reserve(later), later);
+        assert_eq!(bucket.reserve(later), later + Duration::from_millis(100));
+
+        // Half a token refilled leaves half the interval to wait.
+        let mut bucket = TokenBucket::new(10.0, 1);
+        bucket.reserve(now);
+        assert_eq!(bucket.reserve(now + Duration::from_millis(50)), now + Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,169 @@
+use std::time::{Duration, SystemTime};
+
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+
+use crate::CheckError;
+
+/// When failed checks are retried and how long to wait in between.
+///
+/// The wait doubles with every attempt, starting at `base` and capped at
+/// `max`, and is shortened by a random share of up to `jitter` so that
+/// checks failing together do not retry in lockstep. A `Retry-After` header
+/// on a retried response is honored, up to `max`.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub base: Duration,
+    pub max: Duration,
+    pub jitter: f64,
+    /// Unexpected status codes that are retried.
+    pub statuses: Vec<u16>,
+    /// Kinds of error that are retried, as returned by `CheckError::kind`.
+    pub errors: Vec<String>,
+    pub retry_after: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 0,
+            base: Duration::from_millis(100),
+            max: Duration::from_secs(10),
+            jitter: 0.2,
+            statuses: vec![429, 502, 503, 504],
+            errors: ["dns", "connect_refused", "connect_timeout", "connect", "tls", "read_timeout", "body", "other"]
+                .map(String::from)
+                .to_vec(),
+            retry_after: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn retries_on(&self, error: &CheckError) -> bool {
+        match error {
+            CheckError::UnexpectedStatus(code) if self.statuses.contains(code) => true,
+            _ => self.errors.iter().any(|kind| kind == error.kind()),
+        }
+    }
+
+    /// How long to wait after the given failed attempt, counting from zero.
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        let backoff = self.base.saturating_mul(2u32.saturating_pow(attempt)).min(self.max);
+        let backoff = backoff.mul_f64(1.0 - self.jitter.clamp(0.0, 1.0) * rand::thread_rng().gen_range(0.0..1.0));
+        match retry_after {
+            Some(after) if self.retry_after => after.min(self.max).max(backoff),
+            _ => backoff,
+        }
+    }
+}
+
+/// The wait requested by a `Retry-After` header, given either in seconds or
+/// as an HTTP date.
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    match value.parse::<u64>() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => {
+            let at = httpdate::parse_http_date(value).ok()?;
+            Some(at.duration_since(SystemTime::now()).unwrap_or_default())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+
+    use super::{retry_after, RetryPolicy};
+    use crate::CheckError;
+
+    fn without_jitter() -> RetryPolicy {
+        RetryPolicy {
+            base: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        }
+    }
+
+    #[test]
+    fn delay_doubles_up_to_max() {
+        let policy = without_jitter();
+        let delays: Vec<u128> = (0..6).map(|attempt| policy.delay(attempt, None).as_millis()).collect();
+        assert_eq!(delays, [100, 200, 400, 800, 1000, 1000]);
+        assert_eq!(policy.delay(u32::MAX, None), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn jitter_only_shortens_the_delay() {
+        let policy = RetryPolicy {
+            jitter: 0.5,
+            ..without_jitter()
+        };
+        for _ in 0..200 {
+            let delay = policy.delay(2, None);
+            assert!(delay > Duration::from_millis(200) && delay <= Duration::from_millis(400), "{:?}", delay);
+        }
+    }
+
+    #[test]
+    fn retry_after_is_a_floor_capped_at_max() {
+        let policy = without_jitter();
+        assert_eq!(policy.delay(0, Some(Duration::from_millis(700))), Duration::from_millis(700));
+        assert_eq!(policy.delay(0, Some(Duration::from_secs(30))), Duration::from_secs(1));
+        // A shorter Retry-After does not cut the backoff short.
+        assert_eq!(policy.delay(3, Some(Duration::from_millis(10))), Duration::from_millis(800));
+
+        let ignoring = RetryPolicy {
+            retry_after: false,
+            ..without_jitter()
+        };
+        assert_eq!(ignoring.delay(0, Some(Duration::from_millis(700))), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn retries_listed_statuses_and_error_kinds() {
+        let policy = RetryPolicy {
+            statuses: vec![503],
+            errors: vec!["connect_refused".to_string()],
+            ..RetryPolicy::default()
+        };
+        assert!(policy.retries_on(&CheckError::UnexpectedStatus(503)));
+        assert!(!policy.retries_on(&CheckError::UnexpectedStatus(502)));
+        assert!(policy.retries_on(&CheckError::ConnectRefused));
+        assert!(!policy.retries_on(&CheckError::ConnectTimeout));
+        assert!(!policy.retries_on(&CheckError::AssertionFailed("body does not contain 'ok'".to_string())));
+
+        // Statuses are only retried through the status list, not as an error kind.
+        let by_kind = RetryPolicy {
+            statuses: vec![],
+            errors: vec!["unexpected_status".to_string()],
+            ..RetryPolicy::default()
+        };
+        assert!(by_kind.retries_on(&CheckError::UnexpectedStatus(404)));
+    }
+
+    fn headers(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn parses_retry_after() {
+        assert_eq!(retry_after(&headers("120")), Some(Duration::from_secs(120)));
+        assert_eq!(retry_after(&headers(" 0 ")), Some(Duration::ZERO));
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+        assert_eq!(retry_after(&headers("soon")), None);
+
+        let at = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(60));
+        let wait = retry_after(&headers(&at)).unwrap();
+        assert!(wait > Duration::from_secs(55) && wait <= Duration::from_secs(60), "{:?}", wait);
+        // A date in the past means no wait.
+        assert_eq!(retry_after(&headers("Sun, 06 Nov 1994 08:49:37 GMT")), Some(Duration::ZERO));
+    }
+}
@@ -0,0 +1,58 @@
+use std::{convert::Infallible, future::Future, io, net::SocketAddr, sync::Arc};
+
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Request, Response,
+};
+use tokio::runtime::Runtime;
+
+/// A small embedded HTTP server running on its own runtime. The server stops
+/// when this handle is dropped.
+pub struct HttpServer {
+    addr: SocketAddr,
+    _runtime: Runtime,
+}
+
+impl HttpServer {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+pub(crate) fn serve<H, F>(addr: SocketAddr, handler: H) -> io::Result<HttpServer>
+where
+    H: Fn(Request<Body>) -> F + Send + Sync + 'static,
+    F: Future<Output = Response<Body>> + Send + 'static,
+{
+    let listener = std::net::TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()?;
+    let _guard = runtime.enter();
+
+    let handler = Arc::new(handler);
+    let make_service = make_service_fn(move |_| {
+        let handler = Arc::clone(&handler);
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let response = handler(req);
+                async move { Ok::<_, Infallible>(response.await) }
+            }))
+        }
+    });
+    let server = hyper::Server::from_tcp(listener)
+        .map_err(io::Error::other)?
+        .serve(make_service);
+
+    runtime.spawn(async move {
+        if let Err(e) = server.await {
+            eprintln!("http server on {} failed: {}", addr, e);
+        }
+    });
+
+    Ok(HttpServer { addr, _runtime: runtime })
+}
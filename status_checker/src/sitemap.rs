@@ -0,0 +1,224 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    fmt,
+    io::{self, Read},
+};
+
+use flate2::read::GzDecoder;
+use quick_xml::{events::Event, Reader};
+use url::Url;
+
+use crate::{checker::fetch_body, CheckError, Checker, Transport};
+
+#[derive(Debug)]
+pub enum SitemapError {
+    Fetch(String, CheckError),
+    Gzip(String, io::Error),
+    Parse(String, String),
+}
+
+impl fmt::Display for SitemapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SitemapError::Fetch(url, e) => write!(f, "failed to fetch sitemap {}: {}", url, e),
+            SitemapError::Gzip(url, e) => write!(f, "failed to decompress sitemap {}: {}", url, e),
+            SitemapError::Parse(url, e) => write!(f, "invalid sitemap {}: {}", url, e),
+        }
+    }
+}
+
+impl Error for SitemapError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SitemapError::Fetch(_, e) => Some(e),
+            SitemapError::Gzip(_, e) => Some(e),
+            SitemapError::Parse(..) => None,
+        }
+    }
+}
+
+/// The `<loc>` entries of a `<urlset>`, or of a `<sitemapindex>` listing
+/// further sitemaps.
+enum Sitemap {
+    Pages(Vec<String>),
+    Index(Vec<String>),
+}
+
+impl Checker {
+    /// Every page listed in the sitemap at `url`, in document order. Sitemap
+    /// index files are followed to the sitemaps they list, and gzipped
+    /// sitemaps are decompressed.
+    pub fn sitemap(&self, url: &str) -> Result<Vec<String>, SitemapError> {
+        self.runtime.block_on(load(&self.transport, url))
+    }
+}
+
+async fn load(transport: &Transport, url: &str) -> Result<Vec<String>, SitemapError> {
+    let mut pages = vec![];
+    let mut pending = vec![url.to_string()];
+    // An index listing itself, or two indexes listing each other, is read once.
+    let mut seen = HashSet::new();
+
+    while let Some(url) = pending.pop() {
+        if !seen.insert(url.clone()) {
+            continue;
+        }
+        let body = fetch_body(transport, &url)
+            .await
+            .map_err(|e| SitemapError::Fetch(url.clone(), e))?;
+        let body = if body.starts_with(&[0x1f, 0x8b]) {
+            let mut xml = vec![];
+            GzDecoder::new(body.as_slice())
+                .read_to_end(&mut xml)
+                .map_err(|e| SitemapError::Gzip(url.clone(), e))?;
+            xml
+        } else {
+            body
+        };
+
+        match parse(&url, &body)? {
+            Sitemap::Pages(locs) => pages.extend(locs),
+            // Pushed in reverse so that the listed sitemaps are read in order.
+            Sitemap::Index(locs) => pending.extend(locs.into_iter().rev()),
+        }
+    }
+    Ok(pages)
+}
+
+fn parse(url: &str, xml: &[u8]) -> Result<Sitemap, SitemapError> {
+    let invalid = |e: String| SitemapError::Parse(url.to_string(), e);
+    let base = Url::parse(url).map_err(|e| invalid(e.to_string()))?;
+    let mut reader = Reader::from_reader(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut root = None;
+    // Local names of the open elements, outermost first.
+    let mut open: Vec<Vec<u8>> = vec![];
+    let mut loc = None;
+    let mut locs = vec![];
+    let mut buf = vec![];
+    loop {
+        match reader.read_event_into(&mut buf).map_err(|e| invalid(e.to_string()))? {
+            Event::Start(element) => {
+                let name = element.local_name().as_ref().to_vec();
+                // Only the page or sitemap's own location counts, not the
+                // `<image:loc>` of an image extension inside it.
+                if name == b"loc" && matches!(open.last().map(Vec::as_slice), Some(b"url" | b"sitemap")) {
+                    loc = Some(String::new());
+                }
+                if root.is_none() {
+                    root = Some(name.clone());
+                }
+                open.push(name);
+            }
+            Event::Text(text) => {
+                if let Some(loc) = &mut loc {
+                    loc.push_str(&text.unescape().map_err(|e| invalid(e.to_string()))?);
+                }
+            }
+            Event::CData(text) => {
+                if let Some(loc) = &mut loc {
+                    loc.push_str(&String::from_utf8_lossy(&text));
+                }
+            }
+            Event::End(element) => {
+                open.pop();
+                // Locations must be absolute, but relative ones are resolved
+                // against the sitemap rather than dropped.
+                if element.local_name().as_ref() == b"loc"
+                    && let Some(loc) = loc.take()
+                    && let Ok(resolved) = base.join(loc.trim())
+                {
+                    locs.push(resolved.into());
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    match root.as_deref() {
+        Some(b"urlset") => Ok(Sitemap::Pages(locs)),
+        Some(b"sitemapindex") => Ok(Sitemap::Index(locs)),
+        _ => Err(invalid("expected a <urlset> or <sitemapindex> document".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Sitemap, SitemapError};
+
+    const URL: &str = "https://example.com/sitemaps/sitemap.xml";
+
+    #[test]
+    fn parses_urlset() {
+        let xml = br#"<?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <url><loc>https://example.com/</loc><lastmod>2024-01-01</lastmod></url>
+              <url><loc> https://example.com/a?x=1&amp;y=2 </loc></url>
+            </urlset>"#;
+        let Sitemap::Pages(pages) = parse(URL, xml).unwrap() else {
+            panic!("expected a urlset");
+        };
+        assert_eq!(pages, ["https://example.com/", "https://example.com/a?x=1&y=2"]);
+    }
+
+    #[test]
+    fn parses_index() {
+        let xml = br#"<sm:sitemapindex xmlns:sm="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <sm:sitemap><sm:loc>https://example.com/pages.xml.gz</sm:loc></sm:sitemap>
+              <sm:sitemap><sm:loc>https://example.com/posts.xml</sm:loc></sm:sitemap>
+            </sm:sitemapindex>"#;
+        let Sitemap::Index(sitemaps) = parse(URL, xml).unwrap() else {
+            panic!("expected a sitemap index");
+        };
+        assert_eq!(sitemaps, ["https://example.com/pages.xml.gz", "https://example.com/posts.xml"]);
+    }
+
+    #[test]
+    fn reads_cdata_and_resolves_relative_locations() {
+        let xml = br#"<urlset>
+              <url><loc><![CDATA[https://example.com/b?x=1&y=2]]></loc></url>
+              <url><loc>page.html</loc></url>
+              <url><loc>/root.html</loc></url>
+            </urlset>"#;
+        let Sitemap::Pages(pages) = parse(URL, xml).unwrap() else {
+            panic!("expected a urlset");
+        };
+        assert_eq!(
+            pages,
+            ["https://example.com/b?x=1&y=2", "https://example.com/sitemaps/page.html", "https://example.com/root.html"]
+        );
+    }
+
+    #[test]
+    fn rejects_other_documents() {
+        for xml in [&b"<html><body><loc>https://example.com/</loc></body></html>"[..], b"", b"not xml at all"] {
+            assert!(matches!(parse(URL, xml), Err(SitemapError::Parse(..))));
+        }
+        assert!(matches!(parse(URL, b"<urlset><url><loc>x</url></urlset>"), Err(SitemapError::Parse(..))));
+    }
+
+    #[test]
+    fn skips_image_locations() {
+        let xml = br#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"
+                xmlns:image="http://www.google.com/schemas/sitemap-image/1.1">
+              <url>
+                <loc>https://example.com/gallery</loc>
+                <image:image>
+                  <image:loc>https://cdn.example.com/photo.jpg</image:loc>
+                </image:image>
+              </url>
+              <url>
+                <image:image><image:loc>https://cdn.example.com/first.jpg</image:loc></image:image>
+                <loc>https://example.com/about</loc>
+              </url>
+            </urlset>"#;
+        let Sitemap::Pages(pages) = parse(URL, xml).unwrap() else {
+            panic!("expected a urlset");
+        };
+        assert_eq!(pages, ["https://example.com/gallery", "https://example.com/about"]);
+    }
+}
@@ -0,0 +1,213 @@
+use std::{
+    fmt,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use crate::{Target, WebsiteStatus};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Up,
+    /// Up, but slower than the target's `degraded_after` threshold.
+    Degraded,
+    Down,
+}
+
+impl State {
+    pub fn of(target: &Target, status: &WebsiteStatus) -> State {
+        if !status.is_up() {
+            State::Down
+        } else if target.degraded_after.is_some_and(|limit| status.response_time > limit) {
+            State::Degraded
+        } else {
+            State::Up
+        }
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            State::Up => write!(f, "up"),
+            State::Degraded => write!(f, "degraded"),
+            State::Down => write!(f, "down"),
+        }
+    }
+}
+
+/// A change in a target's state between two consecutive checks.
+#[derive(Debug, Clone)]
+pub struct Transition {
+    pub url: String,
+    pub tags: Vec<String>,
+    pub from: State,
+    pub to: State,
+    pub at: SystemTime,
+    /// How long the target was down, set when it recovers from `Down`.
+    pub downtime: Option<Duration>,
+    /// The check that caused the transition.
+    pub status: WebsiteStatus,
+}
+
+#[derive(Debug, Clone)]
+pub enum WatchEvent {
+    Checked(WebsiteStatus),
+    Transition(Transition),
+}
+
+struct Entry {
+    latest: Option<WebsiteStatus>,
+    state: State,
+    down_since: Option<SystemTime>,
+}
+
+/// Latest result and state of every watched target. Targets are assumed to
+/// be up until their first check says otherwise.
+pub(crate) struct Tracker {
+    entries: Mutex<Vec<Entry>>,
+}
+
+impl Tracker {
+    pub(crate) fn new(targets: usize) -> Tracker {
+        let entries = (0..targets)
+            .map(|_| Entry {
+                latest: None,
+                state: State::Up,
+                down_since: None,
+            })
+            .collect();
+        Tracker {
+            entries: Mutex::new(entries),
+        }
+    }
+
+    pub(crate) fn record(&self, index: usize, target: &Target, status: &WebsiteStatus) -> Option<Transition> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = &mut entries[index];
+        entry.latest = Some(status.clone());
+
+        let state = State::of(target, status);
+        if state == entry.state {
+            return None;
+        }
+
+        let from = entry.state;
+        entry.state = state;
+        let downtime = match state {
+            State::Down => {
+                entry.down_since = Some(status.timestamp);
+                None
+            }
+            _ => entry
+                .down_since
+                .take()
+                .map(|since| status.timestamp.duration_since(since).unwrap_or_default()),
+        };
+
+        Some(Transition {
+            url: status.url.clone(),
+            tags: status.tags.clone(),
+            from,
+            to: state,
+            at: status.timestamp,
+            downtime,
+            status: status.clone(),
+        })
+    }
+
+    pub(crate) fn latest(&self) -> Vec<WebsiteStatus> {
+        let entries = self.entries.lock().unwrap();
+        entries.iter().filter_map(|entry| entry.latest.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{State, Tracker};
+    use crate::{CheckError, Target, WebsiteStatus};
+
+    fn target() -> Target {
+        let mut target = Target::new("https://example.com/");
+        target.degraded_after = Some(Duration::from_millis(500));
+        target
+    }
+
+    /// A check finished `at` seconds into the watch, answering in `millis`, or down when `None`.
+    fn status(at: u64, millis: Option<u64>) -> WebsiteStatus {
+        let response_time = Duration::from_millis(millis.unwrap_or(0));
+        WebsiteStatus {
+            url: "https://example.com/".to_string(),
+            action_status: if millis.is_some() { Ok(200) } else { Err(CheckError::UnexpectedStatus(503)) },
+            response_time,
+            total_time: response_time,
+            phases: None,
+            cert_expiry: None,
+            tags: vec![],
+            referrer: None,
+            attempts: vec![],
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_secs(at),
+        }
+    }
+
+    #[test]
+    fn reports_downtime_on_recovery() {
+        let tracker = Tracker::new(1);
+        assert!(tracker.record(0, &target(), &status(0, Some(100))).is_none());
+
+        let down = tracker.record(0, &target(), &status(10, None)).unwrap();
+        assert_eq!((down.from, down.to, down.downtime), (State::Up, State::Down, None));
+
+        let up = tracker.record(0, &target(), &status(70, Some(100))).unwrap();
+        assert_eq!((up.from, up.to), (State::Down, State::Up));
+        assert_eq!(up.downtime, Some(Duration::from_secs(60)));
+        assert_eq!(up.at, SystemTime::UNIX_EPOCH + Duration::from_secs(70));
+    }
+
+    #[test]
+    fn recovering_to_degraded_reports_downtime() {
+        let tracker = Tracker::new(1);
+        tracker.record(0, &target(), &status(10, None)).unwrap();
+
+        let degraded = tracker.record(0, &target(), &status(40, Some(900))).unwrap();
+        assert_eq!((degraded.from, degraded.to), (State::Down, State::Degraded));
+        assert_eq!(degraded.downtime, Some(Duration::from_secs(30)));
+
+        // Once recovered, the next change does not report the same downtime again.
+        let up = tracker.record(0, &target(), &status(50, Some(100))).unwrap();
+        assert_eq!((up.from, up.to, up.downtime), (State::Degraded, State::Up, None));
+    }
+
+    #[test]
+    fn going_down_again_restarts_downtime() {
+        let tracker = Tracker::new(1);
+        tracker.record(0, &target(), &status(0, None)).unwrap();
+        tracker.record(0, &target(), &status(20, Some(900))).unwrap();
+
+        let down = tracker.record(0, &target(), &status(50, None)).unwrap();
+        assert_eq!((down.from, down.to, down.downtime), (State::Degraded, State::Down, None));
+
+        let up = tracker.record(0, &target(), &status(65, Some(100))).unwrap();
+        assert_eq!(up.downtime, Some(Duration::from_secs(15)));
+    }
+
+    #[test]
+    fn unchanged_state_is_not_a_transition() {
+        let tracker = Tracker::new(2);
+        for at in 0..3 {
+            assert!(tracker.record(0, &target(), &status(at, Some(100))).is_none());
+        }
+        tracker.record(1, &target(), &status(0, None)).unwrap();
+        assert!(tracker.record(1, &target(), &status(5, None)).is_none());
+        tracker.record(1, &target(), &status(8, Some(900))).unwrap();
+        assert!(tracker.record(1, &target(), &status(9, Some(800))).is_none());
+
+        // The latest result is kept either way, in target order.
+        let latest = tracker.latest();
+        assert_eq!(latest.len(), 2);
+        assert_eq!(latest[0].timestamp, SystemTime::UNIX_EPOCH + Duration::from_secs(2));
+        assert_eq!(latest[1].response_time, Duration::from_millis(800));
+    }
+}
@@ -0,0 +1,59 @@
+use std::time::{Duration, SystemTime};
+
+use crate::CheckError;
+
+/// Time spent in each phase of the final request, measured when phase
+/// timings are enabled. `redirect` covers every hop before the final one.
+#[derive(Debug, Clone, Default)]
+pub struct Phases {
+    pub dns: Duration,
+    pub connect: Duration,
+    pub tls: Option<Duration>,
+    pub ttfb: Duration,
+    pub download: Duration,
+    pub redirect: Duration,
+}
+
+/// A single request made while checking a target.
+#[derive(Debug, Clone)]
+pub struct Attempt {
+    pub outcome: Result<u16, CheckError>,
+    pub duration: Duration,
+    /// How long the checker waited before trying again, unless this was the last attempt.
+    pub delay: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WebsiteStatus {
+    pub url: String,
+    pub action_status: Result<u16, CheckError>,
+    /// Latency of the final attempt alone.
+    pub response_time: Duration,
+    /// Time spent on the whole check, including earlier attempts and the waits between them.
+    pub total_time: Duration,
+    pub phases: Option<Phases>,
+    /// When the server's TLS certificate expires, for HTTPS targets.
+    pub cert_expiry: Option<SystemTime>,
+    pub tags: Vec<String>,
+    /// The page that linked to this URL, for results found by crawling.
+    pub referrer: Option<String>,
+    /// Every attempt made, the last of which decided `action_status`.
+    pub attempts: Vec<Attempt>,
+    pub timestamp: SystemTime,
+}
+
+impl WebsiteStatus {
+    /// A target is up when it answered with an expected status and passed
+    /// every body assertion.
+    pub fn is_up(&self) -> bool {
+        self.action_status.is_ok()
+    }
+
+    /// The HTTP status the target answered with, if it answered at all.
+    pub fn status_code(&self) -> Option<u16> {
+        match &self.action_status {
+            Ok(code) | Err(CheckError::UnexpectedStatus(code)) => Some(*code),
+            Err(_) => None,
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use std::{fmt, ops::RangeInclusive, str::FromStr, time::Duration};
+
+use regex::Regex;
+use reqwest::{header::HeaderMap, Method};
+use url::Url;
+
+/// Set of HTTP status codes that count as "up", written as a comma-separated
+/// list of codes and ranges such as `200-299,301`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectedStatus(Vec<RangeInclusive<u16>>);
+
+impl ExpectedStatus {
+    /// Any `2xx` status.
+    pub fn success() -> Self {
+        ExpectedStatus(vec![200..=299])
+    }
+
+    pub fn contains(&self, code: u16) -> bool {
+        self.0.iter().any(|range| range.contains(&code))
+    }
+}
+
+impl Default for ExpectedStatus {
+    fn default() -> Self {
+        ExpectedStatus(vec![200..=399])
+    }
+}
+
+impl FromStr for ExpectedStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut ranges = vec![];
+        for part in s.split(',').map(str::trim) {
+            let (low, high) = part.split_once('-').unwrap_or((part, part));
+            let low: u16 = low.trim().parse().map_err(|_| format!("invalid status code '{}'", low.trim()))?;
+            let high: u16 = high.trim().parse().map_err(|_| format!("invalid status code '{}'", high.trim()))?;
+            if !(100..=599).contains(&low) || !(100..=599).contains(&high) || low > high {
+                return Err(format!("invalid status range '{}'", part));
+            }
+            ranges.push(low..=high);
+        }
+        Ok(ExpectedStatus(ranges))
+    }
+}
+
+impl fmt::Display for ExpectedStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self
+            .0
+            .iter()
+            .map(|range| {
+                if range.start() == range.end() {
+                    range.start().to_string()
+                } else {
+                    format!("{}-{}", range.start(), range.end())
+                }
+            })
+            .collect();
+        write!(f, "{}", parts.join(","))
+    }
+}
+
+/// Whether `input` starts with a scheme such as `https://`. A `://` after the
+/// path has begun, as in a query like `?next=https://…`, does not count.
+pub(crate) fn has_scheme(input: &str) -> bool {
+    input.find("://").is_some_and(|at| !input[..at].contains(['/', '?', '#']))
+}
+
+/// Parse a URL as written in a target list into its canonical form, so that
+/// equivalent spellings compare equal. Bare hostnames get `https://`,
+/// internationalized names are converted to punycode, the host is lowercased
+/// and default ports and fragments are dropped.
+pub fn normalize_url(input: &str) -> Result<String, String> {
+    let input = input.trim();
+    let with_scheme;
+    let input = if has_scheme(input) {
+        input
+    } else {
+        with_scheme = format!("https://{}", input);
+        &with_scheme
+    };
+    let mut url = Url::parse(input).map_err(|e| format!("invalid url '{}': {}", input, e))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("unsupported scheme in '{}'", input));
+    }
+    if url.host_str().is_none_or(str::is_empty) {
+        return Err(format!("missing host in '{}'", input));
+    }
+    url.set_fragment(None);
+    Ok(url.into())
+}
+
+#[derive(Debug, Clone)]
+pub enum Assertion {
+    Contains(String),
+    NotContains(String),
+    Matches(Regex),
+    NotMatches(Regex),
+}
+
+impl Assertion {
+    /// Returns a description of the failure if `body` does not satisfy the assertion.
+    pub fn check(&self, body: &str) -> Result<(), String> {
+        match self {
+            Assertion::Contains(text) if !body.contains(text.as_str()) => {
+                Err(format!("body does not contain '{}'", text))
+            }
+            Assertion::NotContains(text) if body.contains(text.as_str()) => {
+                Err(format!("body contains '{}'", text))
+            }
+            Assertion::Matches(re) if !re.is_match(body) => {
+                Err(format!("body does not match /{}/", re))
+            }
+            Assertion::NotMatches(re) if re.is_match(body) => Err(format!("body matches /{}/", re)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A single URL to check, along with how to request it and what counts as up.
+/// `timeout` and `retries` fall back to the checker's settings when unset.
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub url: String,
+    pub method: Method,
+    pub headers: HeaderMap,
+    pub body: Option<String>,
+    pub timeout: Option<Duration>,
+    pub retries: Option<u32>,
+    pub expect: ExpectedStatus,
+    pub assertions: Vec<Assertion>,
+    pub tags: Vec<String>,
+    pub interval: Option<Duration>,
+    /// Response time above which an otherwise healthy target counts as degraded.
+    pub degraded_after: Option<Duration>,
+    /// The page that linked to this target, when it was found by crawling.
+    pub referrer: Option<String>,
+}
+
+impl Target {
+    pub fn new(url: impl Into<String>) -> Self {
+        Target {
+            url: url.into(),
+            method: Method::GET,
+            headers: HeaderMap::new(),
+            body: None,
+            timeout: None,
+            retries: None,
+            expect: ExpectedStatus::default(),
+            assertions: vec![],
+            tags: vec![],
+            interval: None,
+            degraded_after: None,
+            referrer: None,
+        }
+    }
+
+    pub fn expect(mut self, expect: ExpectedStatus) -> Self {
+        self.expect = expect;
+        self
+    }
+
+    pub fn assert(mut self, assertion: Assertion) -> Self {
+        self.assertions.push(assertion);
+        self
+    }
+}
+
+impl From<String> for Target {
+    fn from(url: String) -> Self {
+        Target::new(url)
+    }
+}
+
+impl From<&str> for Target {
+    fn from(url: &str) -> Self {
+        Target::new(url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_url, ExpectedStatus};
+
+    #[test]
+    fn parses_expected_status_ranges() {
+        let expect: ExpectedStatus = " 200-299, 301 ,404".parse().unwrap();
+        assert!(expect.contains(200) && expect.contains(299) && expect.contains(301) && expect.contains(404));
+        assert!(!expect.contains(300) && !expect.contains(302) && !expect.contains(500));
+        assert_eq!(expect.to_string(), "200-299,301,404");
+
+        let default = ExpectedStatus::default();
+        assert!(default.contains(200) && default.contains(399) && !default.contains(400));
+    }
+
+    #[test]
+    fn rejects_bad_expected_status() {
+        assert_eq!("299-200".parse::<ExpectedStatus>().unwrap_err(), "invalid status range '299-200'");
+        assert_eq!("99".parse::<ExpectedStatus>().unwrap_err(), "invalid status range '99'");
+        assert_eq!("200-600".parse::<ExpectedStatus>().unwrap_err(), "invalid status range '200-600'");
+        assert_eq!("70000".parse::<ExpectedStatus>().unwrap_err(), "invalid status code '70000'");
+        assert_eq!("2xx".parse::<ExpectedStatus>().unwrap_err(), "invalid status code '2xx'");
+        assert_eq!("200,".parse::<ExpectedStatus>().unwrap_err(), "invalid status code ''");
+    }
+
+    #[test]
+    fn bare_hosts_get_https() {
+        assert_eq!(normalize_url("example.com").unwrap(), "https://example.com/");
+        assert_eq!(normalize_url("  localhost:8080/health ").unwrap(), "https://localhost:8080/health");
+        assert_eq!(
+            normalize_url("example.com/login?next=https://example.com/home").unwrap(),
+            "https://example.com/login?next=https://example.com/home"
+        );
+        assert_eq!(normalize_url("http://example.com/?next=https://a.test").unwrap(), "http://example.com/?next=https://a.test");
+    }
+
+    #[test]
+    fn equivalent_spellings_compare_equal() {
+        let canonical = normalize_url("https://example.com/a").unwrap();
+        for spelling in ["HTTPS://EXAMPLE.com/a", "https://example.com:443/a", "https://example.com/a#top", "example.com/a"] {
+            assert_eq!(normalize_url(spelling).unwrap(), canonical, "{}", spelling);
+        }
+        assert_eq!(normalize_url("http://example.com:80/").unwrap(), "http://example.com/");
+        assert_eq!(normalize_url("http://example.com:8080/").unwrap(), "http://example.com:8080/");
+    }
+
+    #[test]
+    fn converts_idn_to_punycode() {
+        assert_eq!(normalize_url("https://Bücher.example/").unwrap(), "https://xn--bcher-kva.example/");
+        assert_eq!(normalize_url("bücher.example").unwrap(), "https://xn--bcher-kva.example/");
+    }
+
+    #[test]
+    fn rejects_other_schemes_and_missing_hosts() {
+        assert!(normalize_url("ftp://example.com/").is_err());
+        assert!(normalize_url("https://").is_err());
+        assert!(normalize_url("http://exa mple.com/").is_err());
+    }
+}
@@ -0,0 +1,331 @@
+use std::{
+    io,
+    time::{Duration, Instant, SystemTime},
+};
+
+use hyper::{
+    body::HttpBody,
+    client::conn,
+    header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, HOST, LOCATION, PROXY_AUTHORIZATION, USER_AGENT},
+    Body, HeaderMap, Method, Request,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{self, TcpStream},
+    time::timeout,
+};
+use tokio_native_tls::TlsConnector;
+use url::{Position, Url};
+
+use crate::{cert, retry, CheckError, Phases, Target};
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+pub(crate) struct Traced {
+    /// The URL that answered, after any redirects.
+    pub(crate) url: String,
+    pub(crate) status: u16,
+    pub(crate) content_type: Option<String>,
+    pub(crate) phases: Phases,
+    pub(crate) body: Vec<u8>,
+    pub(crate) cert_expiry: Option<SystemTime>,
+    pub(crate) retry_after: Option<Duration>,
+}
+
+/// Sends requests over a fresh connection each time so that every phase of
+/// the exchange can be timed on its own, the way `curl -w` reports them.
+#[derive(Clone)]
+pub struct Tracer {
+    tls: TlsConnector,
+    timeout: Duration,
+    user_agent: Option<String>,
+    max_redirects: usize,
+}
+
+impl Tracer {
+    pub(crate) fn new(
+        timeout: Duration,
+        user_agent: Option<String>,
+        max_redirects: usize,
+        accept_invalid_certs: bool,
+    ) -> Result<Tracer, native_tls::Error> {
+        let tls = native_tls::TlsConnector::builder()
+            .danger_accept_invalid_certs(accept_invalid_certs)
+            .build()?;
+
+        Ok(Tracer {
+            tls: TlsConnector::from(tls),
+            timeout,
+            user_agent,
+            max_redirects,
+        })
+    }
+
+    pub(crate) async fn send(&self, target: &Target) -> Result<Traced, CheckError> {
+        let mut url = Url::parse(&target.url).map_err(|e| CheckError::InvalidUrl(e.to_string()))?;
+        let mut method = target.method.clone();
+        let mut body = target.body.clone();
+        let mut headers = target.headers.clone();
+        let deadline = Instant::now() + target.timeout.unwrap_or(self.timeout);
+        let mut redirect = Duration::ZERO;
+        let mut redirects = 0;
+
+        loop {
+            let hop_start = Instant::now();
+            let (mut traced, location) = self
+                .send_once(&url, &method, &headers, body.as_deref(), deadline)
+                .await?;
+
+            match location {
+                Some(location) if (300..400).contains(&traced.status) => {
+                    if redirects == self.max_redirects {
+                        return Err(CheckError::TooManyRedirects);
+                    }
+                    redirects += 1;
+                    redirect += hop_start.elapsed();
+                    if matches!(traced.status, 301..=303) {
+                        method = Method::GET;
+                        body = None;
+                        headers.remove(CONTENT_TYPE);
+                        headers.remove(CONTENT_LENGTH);
+                    }
+                    let next = url
+                        .join(&location)
+                        .map_err(|e| CheckError::InvalidUrl(e.to_string()))?;
+                    // Credentials are only sent to the origin they were configured for,
+                    // as the pooled client does.
+                    if next.origin() != url.origin() {
+                        headers.remove(AUTHORIZATION);
+                        headers.remove(PROXY_AUTHORIZATION);
+                        headers.remove(COOKIE);
+                    }
+                    url = next;
+                }
+                _ => {
+                    traced.phases.redirect = redirect;
+                    return Ok(traced);
+                }
+            }
+        }
+    }
+
+    async fn send_once(
+        &self,
+        url: &Url,
+        method: &Method,
+        headers: &HeaderMap,
+        body: Option<&str>,
+        deadline: Instant,
+    ) -> Result<(Traced, Option<String>), CheckError> {
+        let https = match url.scheme() {
+            "http" => false,
+            "https" => true,
+            scheme => return Err(CheckError::InvalidUrl(format!("unsupported scheme '{}'", scheme))),
+        };
+        let host = url
+            .host_str()
+            .ok_or_else(|| CheckError::InvalidUrl("missing host".to_string()))?
+            .to_string();
+        let port = url.port_or_known_default().unwrap_or(if https { 443 } else { 80 });
+        let mut phases = Phases::default();
+        let mut cert_expiry = None;
+
+        let started = Instant::now();
+        let addr = within(deadline, net::lookup_host((host.as_str(), port)))
+            .await
+            .ok_or(CheckError::ConnectTimeout)?
+            .map_err(|e| CheckError::Dns(e.to_string()))?
+            .next()
+            .ok_or_else(|| CheckError::Dns(format!("no addresses found for {}", host)))?;
+        phases.dns = started.elapsed();
+
+        let started = Instant::now();
+        let tcp = within(deadline, TcpStream::connect(addr))
+            .await
+            .ok_or(CheckError::ConnectTimeout)?
+            .map_err(connect_error)?;
+        phases.connect = started.elapsed();
+
+        let stream: Box<dyn Stream> = if https {
+            let started = Instant::now();
+            let tls = within(deadline, self.tls.connect(host.trim_matches(['[', ']']), tcp))
+                .await
+                .ok_or(CheckError::ConnectTimeout)?
+                .map_err(|e| CheckError::Tls(e.to_string()))?;
+            phases.tls = Some(started.elapsed());
+            cert_expiry = tls
+                .get_ref()
+                .peer_certificate()
+                .ok()
+                .flatten()
+                .and_then(|cert| cert.to_der().ok())
+                .and_then(|der| cert::expiry(&der));
+            Box::new(tls)
+        } else {
+            Box::new(tcp)
+        };
+
+        let (mut sender, connection) = conn::handshake(stream)
+            .await
+            .map_err(|e| CheckError::Connect(e.to_string()))?;
+        tokio::spawn(connection);
+
+        let mut request = Request::builder()
+            .method(method)
+            .uri(&url[Position::BeforePath..Position::AfterQuery])
+            .header(HOST, &url[Position::BeforeHost..Position::AfterPort]);
+        if let Some(user_agent) = &self.user_agent {
+            request = request.header(USER_AGENT, user_agent);
+        }
+        if let Some(request_headers) = request.headers_mut() {
+            request_headers.extend(headers.clone());
+        }
+        let request = request
+            .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+            .map_err(|e| CheckError::InvalidUrl(e.to_string()))?;
+
+        let started = Instant::now();
+        let mut response = within(deadline, sender.send_request(request))
+            .await
+            .ok_or(CheckError::ReadTimeout)?
+            .map_err(|e| CheckError::Other(e.to_string()))?;
+        phases.ttfb = started.elapsed();
+
+        let started = Instant::now();
+        let mut body = vec![];
+        while let Some(chunk) = within(deadline, response.body_mut().data())
+            .await
+            .ok_or(CheckError::ReadTimeout)?
+        {
+            body.extend_from_slice(&chunk.map_err(|e| CheckError::Body(e.to_string()))?);
+        }
+        phases.download = started.elapsed();
+
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        let traced = Traced {
+            url: url.to_string(),
+            status: response.status().as_u16(),
+            content_type,
+            phases,
+            body,
+            cert_expiry,
+            retry_after: retry::retry_after(response.headers()),
+        };
+        Ok((traced, location))
+    }
+}
+
+async fn within<F: Future>(deadline: Instant, future: F) -> Option<F::Output> {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    timeout(remaining, future).await.ok()
+}
+
+fn connect_error(e: io::Error) -> CheckError {
+    match e.kind() {
+        io::ErrorKind::ConnectionRefused => CheckError::ConnectRefused,
+        io::ErrorKind::TimedOut => CheckError::ConnectTimeout,
+        _ => CheckError::Connect(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use hyper::{
+        header::{AUTHORIZATION, CONTENT_TYPE, COOKIE, LOCATION},
+        Body, HeaderMap, Method, Response, StatusCode,
+    };
+
+    use super::Tracer;
+    use crate::{server, Target};
+
+    type Seen = Arc<Mutex<Vec<(Method, HeaderMap)>>>;
+
+    /// A local endpoint that redirects `/start` to `location` with `status`
+    /// and records the method and headers of every other request.
+    fn endpoint(status: StatusCode, location: Option<String>) -> (server::HttpServer, Seen) {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let server = {
+            let seen = Arc::clone(&seen);
+            server::serve(SocketAddr::from(([127, 0, 0, 1], 0)), move |req| {
+                let response = if req.uri().path() == "/start" {
+                    let location = location.clone().unwrap_or_else(|| "/end".to_string());
+                    Response::builder().status(status).header(LOCATION, location).body(Body::empty()).unwrap()
+                } else {
+                    seen.lock().unwrap().push((req.method().clone(), req.headers().clone()));
+                    Response::new(Body::empty())
+                };
+                async { response }
+            })
+            .unwrap()
+        };
+        (server, seen)
+    }
+
+    fn send(url: String, method: Method) -> u16 {
+        let mut target = Target::new(url);
+        target.method = method;
+        target.body = Some("{}".to_string());
+        target.headers.insert(AUTHORIZATION, "Bearer secret".parse().unwrap());
+        target.headers.insert(COOKIE, "session=1".parse().unwrap());
+        target.headers.insert(CONTENT_TYPE, "application/json".parse().unwrap());
+
+        let tracer = Tracer::new(Duration::from_secs(5), None, 5, false).unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(tracer.send(&target)).unwrap().status
+    }
+
+    #[test]
+    fn drops_credentials_on_cross_origin_redirect() {
+        let (end, seen) = endpoint(StatusCode::OK, None);
+        let location = format!("http://{}/end", end.local_addr());
+        let (start, _) = endpoint(StatusCode::TEMPORARY_REDIRECT, Some(location));
+
+        assert_eq!(send(format!("http://{}/start", start.local_addr()), Method::PUT), 200);
+        let seen = seen.lock().unwrap();
+        let (method, headers) = &seen[0];
+        assert_eq!(*method, Method::PUT);
+        assert!(headers.get(AUTHORIZATION).is_none() && headers.get(COOKIE).is_none());
+        assert_eq!(headers[CONTENT_TYPE], "application/json");
+    }
+
+    #[test]
+    fn keeps_credentials_on_same_origin_redirect() {
+        let (server, seen) = endpoint(StatusCode::TEMPORARY_REDIRECT, None);
+
+        assert_eq!(send(format!("http://{}/start", server.local_addr()), Method::GET), 200);
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[0].1[AUTHORIZATION], "Bearer secret");
+        assert_eq!(seen[0].1[COOKIE], "session=1");
+    }
+
+    #[test]
+    fn see_other_drops_the_body_and_its_content_type() {
+        let (server, seen) = endpoint(StatusCode::SEE_OTHER, None);
+
+        assert_eq!(send(format!("http://{}/start", server.local_addr()), Method::POST), 200);
+        let seen = seen.lock().unwrap();
+        let (method, headers) = &seen[0];
+        assert_eq!(*method, Method::GET);
+        assert!(headers.get(CONTENT_TYPE).is_none());
+        assert_eq!(headers[AUTHORIZATION], "Bearer secret");
+    }
+}
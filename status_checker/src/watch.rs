@@ -0,0 +1,236 @@
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+use tokio::runtime::Runtime;
+
+use crate::{
+    checker::Workers,
+    queue::{Job, JobQueue},
+    state::Tracker,
+    Checker, Target, WatchEvent, WebsiteStatus,
+};
+
+/// Targets ordered by when they are next due, shared by the watch threads.
+struct Schedule {
+    queue: Mutex<BinaryHeap<Reverse<(Instant, usize)>>>,
+    ready: Condvar,
+}
+
+impl Schedule {
+    fn push(&self, due: Instant, index: usize) {
+        self.queue.lock().unwrap().push(Reverse((due, index)));
+        self.ready.notify_one();
+    }
+
+    fn next(&self, stop: &AtomicBool) -> Option<usize> {
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+            if stop.load(Ordering::Relaxed) {
+                return None;
+            }
+            let now = Instant::now();
+            let wait = match queue.peek() {
+                Some(Reverse((due, _))) if *due <= now => {
+                    return queue.pop().map(|Reverse((_, index))| index);
+                }
+                Some(Reverse((due, _))) => *due - now,
+                None => Duration::from_secs(1),
+            };
+            // Wake up at least once a second so a stopped watch is noticed.
+            queue = self.ready.wait_timeout(queue, wait.min(Duration::from_secs(1))).unwrap().0;
+        }
+    }
+}
+
+/// A running watch. Yields every result as it arrives, followed by a
+/// transition event whenever a target changes state, and keeps the most
+/// recent result for each target. Checks stop when the watch is dropped.
+pub struct Watch {
+    rx: mpsc::Receiver<WatchEvent>,
+    tracker: Arc<Tracker>,
+    stop: Arc<AtomicBool>,
+    workers: Workers,
+    _feeder: JoinHandle<()>,
+    _runtime: Arc<Runtime>,
+}
+
+impl Watch {
+    /// The latest result for every target that has been checked at least once,
+    /// in the order the targets were given.
+    pub fn latest(&self) -> Vec<WebsiteStatus> {
+        self.tracker.latest()
+    }
+}
+
+impl Iterator for Watch {
+    type Item = WatchEvent;
+
+    fn next(&mut self) -> Option<WatchEvent> {
+        match self.rx.recv() {
+            Ok(event) => Some(event),
+            Err(_) => {
+                self.workers.join();
+                None
+            }
+        }
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A random slice of `interval`, up to `jitter` times its length.
+fn jitter_of(interval: Duration, jitter: f64) -> Duration {
+    if jitter <= 0.0 {
+        return Duration::ZERO;
+    }
+    interval.mul_f64(rand::thread_rng().gen_range(0.0..jitter))
+}
+
+impl Checker {
+    /// Check every target repeatedly on its own interval, falling back to the
+    /// checker's default interval for targets that do not set one. Due targets
+    /// go through a job queue, so the per-host and rate limits apply as in `check`.
+    pub fn watch<I>(&self, targets: I) -> Watch
+    where
+        I: IntoIterator,
+        I::Item: Into<Target>,
+    {
+        let targets: Arc<Vec<Target>> = Arc::new(targets.into_iter().map(Into::into).collect());
+        let tracker = Arc::new(Tracker::new(targets.len()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = mpsc::channel();
+
+        let schedule = Arc::new(Schedule {
+            queue: Mutex::new(BinaryHeap::new()),
+            ready: Condvar::new(),
+        });
+        // Spread the first round of checks out instead of firing them all at once.
+        let start = Instant::now();
+        for (index, target) in targets.iter().enumerate() {
+            schedule.push(start + jitter_of(self.interval_of(target), self.jitter), index);
+        }
+
+        // Targets are queued as they fall due, until the watch is dropped.
+        let job_queue = Arc::new(JobQueue::open(self.host_limits.clone(), self.runtime.handle().clone()));
+        let feeder = {
+            let schedule = Arc::clone(&schedule);
+            let job_queue = Arc::clone(&job_queue);
+            let targets = Arc::clone(&targets);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                while let Some(index) = schedule.next(&stop) {
+                    job_queue.push(index, targets[index].clone());
+                }
+                job_queue.close();
+            })
+        };
+
+        let round = Round {
+            schedule,
+            intervals: targets.iter().map(|target| self.interval_of(target)).collect(),
+            jitter: self.jitter,
+            tracker: Arc::clone(&tracker),
+        };
+        let workers = self.spawn_workers(job_queue, None, move |job, status| round.finish(&tx, job, status));
+
+        Watch {
+            rx,
+            tracker,
+            stop,
+            workers,
+            _feeder: feeder,
+            _runtime: Arc::clone(&self.runtime),
+        }
+    }
+
+    fn interval_of(&self, target: &Target) -> Duration {
+        target.interval.unwrap_or(self.interval)
+    }
+}
+
+/// What happens after a watched target has been checked, shared by the workers.
+struct Round {
+    schedule: Arc<Schedule>,
+    intervals: Vec<Duration>,
+    jitter: f64,
+    tracker: Arc<Tracker>,
+}
+
+impl Round {
+    /// Schedule the target's next check, then record `status` and forward it,
+    /// plus any resulting transition, to the watch. Returns `false` once the
+    /// watch has been dropped.
+    fn finish(&self, tx: &mpsc::Sender<WatchEvent>, job: &Job, status: WebsiteStatus) -> bool {
+        let interval = self.intervals[job.index];
+        self.schedule.push(Instant::now() + interval + jitter_of(interval, self.jitter), job.index);
+
+        let transition = self.tracker.record(job.index, &job.target, &status);
+        if tx.send(WatchEvent::Checked(status)).is_err() {
+            return false;
+        }
+        match transition {
+            Some(transition) => tx.send(WatchEvent::Transition(transition)).is_ok(),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use hyper::{Body, Response};
+
+    use crate::{server, Checker, Engine};
+
+    #[test]
+    fn checks_stop_once_the_watch_is_dropped() {
+        for engine in [Engine::Threads, Engine::Async] {
+            let requests = Arc::new(AtomicUsize::new(0));
+            let server = {
+                let requests = Arc::clone(&requests);
+                server::serve(SocketAddr::from(([127, 0, 0, 1], 0)), move |_| {
+                    requests.fetch_add(1, Ordering::SeqCst);
+                    async { Response::new(Body::empty()) }
+                })
+                .unwrap()
+            };
+            let checker = Checker::builder()
+                .engine(engine)
+                .interval(Duration::from_millis(20))
+                .jitter(0.0)
+                .build()
+                .unwrap();
+
+            let mut watch = checker.watch([format!("http://{}/", server.local_addr())]);
+            assert!(watch.next().is_some());
+            drop(watch);
+            // Let a check already in flight finish before counting.
+            thread::sleep(Duration::from_millis(200));
+            let after_drop = requests.load(Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(300));
+            assert_eq!(requests.load(Ordering::SeqCst), after_drop, "{:?}", engine);
+        }
+    }
+}
@@ -0,0 +1,357 @@
+use std::{
+    io,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use reqwest::{header::HeaderMap, Client};
+use serde_json::{json, Map, Value};
+use tokio::{runtime::Runtime, task::JoinHandle};
+
+use crate::{State, Transition};
+
+/// A URL to POST a JSON payload to when a target enters one of the `on` states.
+///
+/// String values in `payload` may contain `{{placeholders}}` which are filled
+/// from the transition. A string consisting of a single placeholder is
+/// replaced by the value itself, so `"{{status_code}}"` becomes a number.
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    pub url: String,
+    pub headers: HeaderMap,
+    pub timeout: Duration,
+    pub retries: u32,
+    pub on: Vec<State>,
+    pub payload: Value,
+}
+
+impl Webhook {
+    pub fn new(url: impl Into<String>) -> Self {
+        Webhook {
+            url: url.into(),
+            headers: HeaderMap::new(),
+            timeout: Duration::from_secs(10),
+            retries: 2,
+            on: vec![State::Down],
+            payload: default_payload(),
+        }
+    }
+
+    pub fn render(&self, transition: &Transition) -> Value {
+        render(&self.payload, &variables(transition))
+    }
+}
+
+fn default_payload() -> Value {
+    json!({
+        "url": "{{url}}",
+        "state": "{{state}}",
+        "previous_state": "{{previous_state}}",
+        "status_code": "{{status_code}}",
+        "error_kind": "{{error_kind}}",
+        "error": "{{error}}",
+        "response_time_ms": "{{response_time_ms}}",
+        "downtime_secs": "{{downtime_secs}}",
+        "timestamp": "{{timestamp}}",
+        "tags": "{{tags}}",
+    })
+}
+
+fn variables(transition: &Transition) -> Map<String, Value> {
+    let status = &transition.status;
+    let (status_code, error_kind, error) = match &status.action_status {
+        Ok(code) => (json!(code), Value::Null, Value::Null),
+        Err(e) => (
+            status.status_code().map_or(Value::Null, |code| json!(code)),
+            json!(e.kind()),
+            json!(e.to_string()),
+        ),
+    };
+    let timestamp = transition
+        .at
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+
+    let mut vars = Map::new();
+    vars.insert("url".to_string(), json!(transition.url));
+    vars.insert("state".to_string(), json!(transition.to.to_string()));
+    vars.insert("previous_state".to_string(), json!(transition.from.to_string()));
+    vars.insert("status_code".to_string(), status_code);
+    vars.insert("error_kind".to_string(), error_kind);
+    vars.insert("error".to_string(), error);
+    vars.insert("response_time_ms".to_string(), json!(status.response_time.as_millis() as u64));
+    vars.insert(
+        "downtime_secs".to_string(),
+        transition.downtime.map_or(Value::Null, |downtime| json!(downtime.as_secs())),
+    );
+    vars.insert("timestamp".to_string(), json!(timestamp));
+    vars.insert("tags".to_string(), json!(transition.tags));
+    vars
+}
+
+fn render(template: &Value, vars: &Map<String, Value>) -> Value {
+    match template {
+        Value::String(text) => {
+            if let Some(name) = text.strip_prefix("{{").and_then(|rest| rest.strip_suffix("}}"))
+                && let Some(value) = vars.get(name.trim())
+            {
+                return value.clone();
+            }
+            Value::String(substitute(text, vars))
+        }
+        Value::Array(items) => Value::Array(items.iter().map(|item| render(item, vars)).collect()),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| (key.clone(), render(value, vars)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+fn substitute(text: &str, vars: &Map<String, Value>) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        let name = rest[start + 2..start + end].trim();
+        out.push_str(&rest[..start]);
+        match vars.get(name) {
+            Some(Value::String(value)) => out.push_str(value),
+            Some(Value::Null) => {}
+            Some(value) => out.push_str(&value.to_string()),
+            None => out.push_str(&rest[start..start + end + 2]),
+        }
+        rest = &rest[start + end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Delivers transitions to webhooks in the background, so that a slow or
+/// unreachable endpoint never holds up the checks themselves.
+pub struct WebhookSink {
+    hooks: Vec<Webhook>,
+    client: Client,
+    runtime: Runtime,
+    pending: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl WebhookSink {
+    pub fn new(hooks: Vec<Webhook>) -> io::Result<WebhookSink> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+
+        Ok(WebhookSink {
+            hooks,
+            client: Client::new(),
+            runtime,
+            pending: Mutex::new(vec![]),
+        })
+    }
+
+    pub fn notify(&self, transition: &Transition) {
+        for hook in self.hooks.iter().filter(|hook| hook.on.contains(&transition.to)) {
+            let hook = hook.clone();
+            let client = self.client.clone();
+            let payload = hook.render(transition);
+
+            let delivery = self.runtime.spawn(async move {
+                if let Err(e) = deliver(&client, &hook, &payload).await {
+                    eprintln!("webhook {} failed: {}", hook.url, e);
+                }
+            });
+            let mut pending = self.pending.lock().unwrap();
+            pending.retain(|delivery| !delivery.is_finished());
+            pending.push(delivery);
+        }
+    }
+
+    /// Wait until every delivery started so far has succeeded or run out of retries.
+    pub fn flush(&self) {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        for delivery in pending {
+            let _ = self.runtime.block_on(delivery);
+        }
+    }
+}
+
+async fn deliver(client: &Client, hook: &Webhook, payload: &Value) -> Result<(), String> {
+    let mut attempt = 0;
+    loop {
+        let res = client
+            .post(&hook.url)
+            .headers(hook.headers.clone())
+            .timeout(hook.timeout)
+            .json(payload)
+            .send()
+            .await;
+
+        let error = match res {
+            Ok(resp) if resp.status().is_success() => return Ok(()),
+            Ok(resp) => format!("server responded with {}", resp.status()),
+            Err(e) => e.to_string(),
+        };
+
+        if attempt == hook.retries {
+            return Err(error);
+        }
+        attempt += 1;
+        tokio::time::sleep(Duration::from_millis(500) * attempt).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant, SystemTime},
+    };
+
+    use hyper::{body, Body, Response, StatusCode};
+    use serde_json::{json, Map, Value};
+
+    use super::{substitute, Webhook, WebhookSink};
+    use crate::{server, Attempt, CheckError, State, Transition, WebsiteStatus};
+
+    fn transition(url: &str) -> Transition {
+        let status = WebsiteStatus {
+            url: url.to_string(),
+            action_status: Err(CheckError::UnexpectedStatus(503)),
+            response_time: Duration::from_millis(120),
+            total_time: Duration::from_millis(120),
+            phases: None,
+            cert_expiry: None,
+            tags: vec!["prod".to_string()],
+            referrer: None,
+            attempts: vec![Attempt {
+                outcome: Err(CheckError::UnexpectedStatus(503)),
+                duration: Duration::from_millis(120),
+                delay: None,
+            }],
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        };
+        Transition {
+            url: url.to_string(),
+            tags: status.tags.clone(),
+            from: State::Up,
+            to: State::Down,
+            at: status.timestamp,
+            downtime: None,
+            status,
+        }
+    }
+
+    /// A local endpoint answering with `status` after `delay`, recording every body it receives.
+    fn endpoint(status: StatusCode, delay: Duration) -> (server::HttpServer, Arc<Mutex<Vec<Value>>>) {
+        let received = Arc::new(Mutex::new(vec![]));
+        let server = {
+            let received = Arc::clone(&received);
+            server::serve(SocketAddr::from(([127, 0, 0, 1], 0)), move |req| {
+                let received = Arc::clone(&received);
+                async move {
+                    let bytes = body::to_bytes(req.into_body()).await.unwrap();
+                    received.lock().unwrap().push(serde_json::from_slice(&bytes).unwrap());
+                    tokio::time::sleep(delay).await;
+                    Response::builder().status(status).body(Body::empty()).unwrap()
+                }
+            })
+            .unwrap()
+        };
+        (server, received)
+    }
+
+    #[test]
+    fn single_placeholder_keeps_its_type() {
+        let mut hook = Webhook::new("http://127.0.0.1:1/");
+        hook.payload = json!({
+            "code": "{{status_code}}",
+            "tags": "{{ tags }}",
+            "downtime": "{{downtime_secs}}",
+            "nested": ["{{response_time_ms}}"],
+            "fixed": 7,
+        });
+        let rendered = hook.render(&transition("https://example.com/"));
+        assert_eq!(
+            rendered,
+            json!({ "code": 503, "tags": ["prod"], "downtime": null, "nested": [120], "fixed": 7 })
+        );
+    }
+
+    #[test]
+    fn substitute_fills_text() {
+        let mut vars = Map::new();
+        vars.insert("url".to_string(), json!("https://example.com/"));
+        vars.insert("status_code".to_string(), json!(503));
+        vars.insert("downtime_secs".to_string(), Value::Null);
+        assert_eq!(
+            substitute("{{url}} answered {{ status_code }}{{downtime_secs}}", &vars),
+            "https://example.com/ answered 503"
+        );
+        assert_eq!(substitute("{{unknown}} and {{unclosed", &vars), "{{unknown}} and {{unclosed");
+    }
+
+    #[test]
+    fn delivers_rendered_payload() {
+        let (server, received) = endpoint(StatusCode::OK, Duration::ZERO);
+        let mut hook = Webhook::new(format!("http://{}/hook", server.local_addr()));
+        hook.payload = json!({ "text": "{{url}} is {{state}}", "code": "{{status_code}}" });
+
+        let sink = WebhookSink::new(vec![hook]).unwrap();
+        sink.notify(&transition("https://example.com/"));
+        sink.flush();
+
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![json!({ "text": "https://example.com/ is down", "code": 503 })]
+        );
+    }
+
+    #[test]
+    fn skips_states_not_subscribed_to() {
+        let (server, received) = endpoint(StatusCode::OK, Duration::ZERO);
+        let mut hook = Webhook::new(format!("http://{}/hook", server.local_addr()));
+        hook.on = vec![State::Up];
+
+        let sink = WebhookSink::new(vec![hook]).unwrap();
+        sink.notify(&transition("https://example.com/"));
+        sink.flush();
+
+        assert!(received.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn retries_server_errors() {
+        let (server, received) = endpoint(StatusCode::SERVICE_UNAVAILABLE, Duration::ZERO);
+        let mut hook = Webhook::new(format!("http://{}/hook", server.local_addr()));
+        hook.retries = 2;
+
+        let sink = WebhookSink::new(vec![hook]).unwrap();
+        sink.notify(&transition("https://example.com/"));
+        sink.flush();
+
+        assert_eq!(received.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn gives_up_after_timeout() {
+        let (server, received) = endpoint(StatusCode::OK, Duration::from_secs(5));
+        let mut hook = Webhook::new(format!("http://{}/hook", server.local_addr()));
+        hook.timeout = Duration::from_millis(200);
+        hook.retries = 0;
+
+        let sink = WebhookSink::new(vec![hook]).unwrap();
+        let start = Instant::now();
+        sink.notify(&transition("https://example.com/"));
+        sink.flush();
+
+        assert!(start.elapsed() < Duration::from_secs(2), "took {:?}", start.elapsed());
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+}